@@ -19,6 +19,10 @@ impl Readline {
             buffer: String::with_capacity(128),
         }
     }
+
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.prompt = prompt.to_owned();
+    }
 }
 
 impl Read for Readline {
@@ -85,3 +89,42 @@ impl io::BufRead for Readline {
         self.buffer.drain(0..amt);
     }
 }
+
+// Standard input, read through the line editor when it's interactive
+pub enum Console {
+    Stdin(io::BufReader<io::Stdin>),
+    Readline(Readline),
+}
+
+impl Console {
+    pub fn set_prompt(&mut self, prompt: &str) {
+        if let Console::Readline(ref mut readline) = *self {
+            readline.set_prompt(prompt);
+        }
+    }
+}
+
+impl Read for Console {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Console::Stdin(ref mut stdin) => stdin.read(buf),
+            Console::Readline(ref mut readline) => readline.read(buf),
+        }
+    }
+}
+
+impl io::BufRead for Console {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match *self {
+            Console::Stdin(ref mut stdin) => stdin.fill_buf(),
+            Console::Readline(ref mut readline) => readline.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match *self {
+            Console::Stdin(ref mut stdin) => stdin.consume(amt),
+            Console::Readline(ref mut readline) => readline.consume(amt),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+#![allow(dead_code)]
+
+// A concrete syntax tree, which keeps everything `parser` throws away: comments,
+// whitespace and the exact spelling of every token. Printing one gives back the
+// source it was read from, so tools can rewrite code without mangling it.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::vec;
+
+use combine::{Parser, ParseError, StreamOnce, parser};
+
+use diagnostic::Diagnostic;
+use lexer;
+use parser;
+use span::Span;
+use stream::{StringStream, TokenStream};
+use token::Token;
+use types::Expr;
+
+// A token, the whitespace and comments before it, and its text as written
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leaf {
+    pub trivia: String,
+    pub token: Token,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    // A literal, symbol or keyword
+    Atom(Leaf),
+    // 'x, `x, ~x, ~@x and #_x with one form, or ^meta x with two
+    Prefix(Leaf, Vec<Node>),
+    // (...), [...], {...}, #{...} and #(...)
+    Delimited(Leaf, Vec<Node>, Leaf),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cst {
+    pub nodes: Vec<Node>,
+    // Whitespace and comments after the last form
+    pub trailing: String,
+}
+
+pub fn parse_cst(input: StringStream) -> Result<Cst, Diagnostic> {
+    let (leaves, trailing, end) = lex_leaves(input)?;
+    let mut leaves = leaves.into_iter().peekable();
+    let mut nodes = Vec::new();
+    while leaves.peek().is_some() {
+        nodes.push(node(&mut leaves, &end)?);
+    }
+    Ok(Cst { nodes, trailing })
+}
+
+// Lex every token along with the text it and the trivia before it were read from
+fn lex_leaves(mut input: StringStream) -> Result<(Vec<Leaf>, String, Span), Diagnostic> {
+    let mut leaves = Vec::new();
+    loop {
+        let start = input.offset();
+        input = parser(lexer::whitespace).parse(input).map(|(_, rest)| rest).map_err(lex_error)?;
+        let trivia = input.slice(start, input.offset());
+
+        if input.clone().uncons().is_err() {
+            return Ok((leaves, trivia, input.position()));
+        }
+
+        let start = input.offset();
+        let ((token, span), rest) = parser(lexer::spanned_token).parse(input).map_err(lex_error)?;
+        let text = rest.slice(start, rest.offset());
+        leaves.push(Leaf { trivia, token, text, span });
+        input = rest;
+    }
+}
+
+fn lex_error(err: ParseError<StringStream>) -> Diagnostic {
+    Diagnostic::from_parse_error(&err)
+}
+
+fn node(leaves: &mut Peekable<vec::IntoIter<Leaf>>, end: &Span) -> Result<Node, Diagnostic> {
+    let leaf = leaves.next().ok_or_else(|| Diagnostic::new(end.clone(), "unexpected end of input"))?;
+
+    if let Some(closer) = leaf.token.closer() {
+        let mut children = Vec::new();
+        loop {
+            match leaves.peek().map(|next| next.token.clone()) {
+                Some(ref token) if *token == closer => break,
+                Some(ref token) if token.is_closer() => {
+                    let found = leaves.next().unwrap();
+                    let message = format!(
+                        "unclosed `{}`: expected `{}`, found `{}` at {}",
+                        leaf.text, closer.source(), found.text, found.span.start
+                    );
+                    return Err(Diagnostic::new(leaf.span, message));
+                }
+                Some(_) => children.push(node(leaves, end)?),
+                None => {
+                    let message = format!("unclosed `{}`: expected `{}`, found end of input", leaf.text, closer.source());
+                    return Err(Diagnostic::new(leaf.span, message));
+                }
+            }
+        }
+        let close = leaves.next().unwrap();
+        return Ok(Node::Delimited(leaf, children, close));
+    }
+
+    match leaf.token {
+        Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing | Token::Discard => {
+            let form = node(leaves, end)?;
+            Ok(Node::Prefix(leaf, vec![form]))
+        }
+        Token::Meta => {
+            let meta = node(leaves, end)?;
+            let form = node(leaves, end)?;
+            Ok(Node::Prefix(leaf, vec![meta, form]))
+        }
+        ref token if token.is_closer() => {
+            let message = format!("unmatched `{}`", leaf.text);
+            Err(Diagnostic::new(leaf.span, message))
+        }
+        _ => Ok(Node::Atom(leaf)),
+    }
+}
+
+impl Node {
+    // The leaves of this node in source order
+    pub fn leaves(&self) -> Vec<&Leaf> {
+        let mut leaves = Vec::new();
+        self.collect_leaves(&mut leaves);
+        leaves
+    }
+
+    fn collect_leaves<'a>(&'a self, leaves: &mut Vec<&'a Leaf>) {
+        match *self {
+            Node::Atom(ref leaf) => leaves.push(leaf),
+            Node::Prefix(ref prefix, ref children) => {
+                leaves.push(prefix);
+                for child in children {
+                    child.collect_leaves(leaves);
+                }
+            }
+            Node::Delimited(ref open, ref children, ref close) => {
+                leaves.push(open);
+                for child in children {
+                    child.collect_leaves(leaves);
+                }
+                leaves.push(close);
+            }
+        }
+    }
+}
+
+impl Cst {
+    // Lower to the `Expr` tree `parser` would have produced from the same source
+    pub fn lower(&self) -> Result<Vec<Expr>, ParseError<TokenStream>> {
+        let tokens = self.nodes.iter()
+            .flat_map(Node::leaves)
+            .map(|leaf| (leaf.token.clone(), leaf.span.clone()))
+            .collect::<Vec<_>>();
+        parser::parse(TokenStream::new(tokens.into_iter())).map(|(exprs, _)| exprs)
+    }
+}
+
+impl fmt::Display for Leaf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.trivia, self.text)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for leaf in self.leaves() {
+            write!(f, "{}", leaf)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for node in &self.nodes {
+            write!(f, "{}", node)?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CORPUS: &[&str] = &[
+        "",
+        "   \n\t",
+        "(+ 1 2)",
+        "; a comment\n(def x 0xFF) ; trailing\n",
+        "(def big 1_000_000)\n(def tiny 1e-3)\n(def half .5)\n(def neg -0b101)",
+        "#| block\n   #| nested |# comment |#\n(print \"hi\\n\\u{e9}\" #\"C:\\raw\")",
+        "[\\a \\space \\u00e9 :key ns/sym #t #f nil]",
+        "{:a 1\n :b [2 3]} #{1 2} #(+ % %2)",
+        "`(when ~test ~@body) '(quoted) #_(discarded form) ^:private ^{:doc \"x\"} [x]",
+        "(fn add [a b]\r\n  (+ a b))\r\n",
+        "(let [xs '(1 2 3)]\n  ;; nested comment\n  (first xs))   ",
+    ];
+
+    fn cst(source: &str) -> Cst {
+        parse_cst(StringStream::new(source)).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for source in CORPUS {
+            assert_eq!(*source, cst(source).to_string());
+        }
+    }
+
+    #[test]
+    fn lower() {
+        for source in CORPUS {
+            let (tokens, _) = lexer::lex(StringStream::new(source)).unwrap();
+            let (exprs, _) = parser::parse(TokenStream::new(tokens.into_iter())).unwrap();
+            assert_eq!(exprs, cst(source).lower().unwrap());
+        }
+    }
+
+    #[test]
+    fn structure() {
+        let cst = cst("; hex\n(f 0xFF)");
+        match cst.nodes[0] {
+            Node::Delimited(ref open, ref children, ref close) => {
+                assert_eq!("; hex\n", open.trivia);
+                assert_eq!("0xFF", children[1].leaves()[0].text);
+                assert_eq!(Token::from(255), children[1].leaves()[0].token);
+                assert_eq!(")", close.text);
+            }
+            ref node => panic!("expected a list, found {:?}", node),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let err = |source| parse_cst(StringStream::new(source)).unwrap_err().to_string();
+        assert_eq!("1:1: unclosed `(`: expected `)`, found `]` at 1:4", err("(a ]"));
+        assert_eq!("1:1: unclosed `#{`: expected `}`, found end of input", err("#{a"));
+        assert_eq!("1:3: unmatched `)`", err("a )"));
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use combine::{ParseError, StreamOnce};
+use combine::primitives::{Error, Info};
+
+use span::Span;
+
+// A syntax error found while reading a source, which doesn't stop the rest of
+// the source from being read
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(span: Span, message: S) -> Self {
+        Diagnostic { span, message: message.into() }
+    }
+
+    pub fn from_parse_error<I>(err: &ParseError<I>) -> Self
+    where
+        I: StreamOnce<Position = Span>,
+        I::Item: fmt::Display,
+        I::Range: fmt::Display,
+    {
+        Diagnostic::new(err.position.clone(), describe(&err.errors))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+// Summarise combine's errors as e.g. "expected `)` or `]`, found `}`"
+pub fn describe<T: fmt::Display, R: fmt::Display>(errors: &[Error<T, R>]) -> String {
+    let mut messages = Vec::new();
+    let mut expected = Vec::new();
+    let mut found = None;
+
+    for error in errors {
+        match *error {
+            Error::Unexpected(ref info) => found = Some(quote(info)),
+            Error::Expected(ref info) => {
+                let info = quote(info);
+                if !expected.contains(&info) {
+                    expected.push(info);
+                }
+            }
+            Error::Message(ref info) => messages.push(info.to_string()),
+            Error::Other(ref err) => messages.push(err.to_string()),
+        }
+    }
+
+    if !messages.is_empty() {
+        return messages.join("; ");
+    }
+
+    let found = found.unwrap_or_else(|| "invalid syntax".into());
+    match expected.split_last() {
+        Some((last, [])) => format!("expected {}, found {}", last, found),
+        Some((last, rest)) => format!("expected {} or {}, found {}", rest.join(", "), last, found),
+        None => format!("unexpected {}", found),
+    }
+}
+
+// Tokens are quoted as source text, descriptions like "end of input" are not
+fn quote<T: fmt::Display, R: fmt::Display>(info: &Info<T, R>) -> String {
+    match *info {
+        Info::Token(_) | Info::Range(_) => format!("`{}`", info),
+        _ => info.to_string(),
+    }
+}
@@ -35,7 +35,7 @@ impl Env {
 
     pub fn define(&self, symbol: &str, value: Expr) -> Symbol {
         (*self.0).borrow_mut().symbols.insert(symbol.to_string(), value);
-        Symbol(symbol.to_string())
+        Symbol::parse(symbol)
     }
 }
 
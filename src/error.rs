@@ -1,12 +1,25 @@
 use combine;
 // use std::fs;
+use std::fmt;
 use std::io;
+use diagnostic::Diagnostic;
+use span::Span;
 use stream::{StringStream, TokenStream};
 
 #[derive(Debug, ErrorChain)]
 pub enum ErrorKind {
     Msg(String),
 
+    #[error_chain(custom)]
+    #[error_chain(description = "located_description")]
+    #[error_chain(display = "located_display")]
+    Located(Span, String),
+
+    #[error_chain(custom)]
+    #[error_chain(description = "syntax_description")]
+    #[error_chain(display = "syntax_display")]
+    Syntax(Vec<Diagnostic>),
+
     // #[error_chain(custom)]
     // #[error_chain(description = r#"|_| "undefined symbol""#)]
     // #[error_chain(display = r#"|t| write!(f, "undefined symbol {}", t)"#)]
@@ -21,8 +34,11 @@ pub enum ErrorKind {
     #[error_chain(foreign)]
     Parse(combine::ParseError<TokenStream>),
 
+    // The input ended part way through a form, which more input might complete
     #[error_chain(custom)]
-    Eof,
+    #[error_chain(description = "incomplete_description")]
+    #[error_chain(display = "incomplete_display")]
+    Incomplete(Span),
 
     #[error_chain(custom)]
     Exit(i32),
@@ -32,3 +48,48 @@ pub enum ErrorKind {
     // #[error_chain(display = r#"|f, value, type| write!(f, "type error: received {}, expected {}", value, type)"#)]
     // Type(Expr, String),
 }
+
+impl Error {
+    // Attach a source location to an error message which doesn't have one yet
+    pub fn at(self, span: Option<&Span>) -> Self {
+        match (self.0, span) {
+            (ErrorKind::Msg(msg), Some(span)) => Error(ErrorKind::Located(span.clone(), msg), self.1),
+            (kind, _) => Error(kind, self.1),
+        }
+    }
+
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self.0, ErrorKind::Incomplete(_))
+    }
+}
+
+fn located_description(_span: &Span, _msg: &str) -> &'static str {
+    "located error"
+}
+
+fn located_display(f: &mut fmt::Formatter, span: &Span, msg: &str) -> fmt::Result {
+    write!(f, "{}: {}", span, msg)
+}
+
+fn incomplete_description(_span: &Span) -> &'static str {
+    "incomplete form"
+}
+
+fn incomplete_display(f: &mut fmt::Formatter, span: &Span) -> fmt::Result {
+    write!(f, "{}: incomplete form, expected more input", span)
+}
+
+fn syntax_description(_diagnostics: &[Diagnostic]) -> &'static str {
+    "syntax errors"
+}
+
+// One diagnostic per line
+fn syntax_display(f: &mut fmt::Formatter, diagnostics: &[Diagnostic]) -> fmt::Result {
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{}", diagnostic)?;
+    }
+    Ok(())
+}
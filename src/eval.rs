@@ -1,54 +1,109 @@
+use std::collections::HashMap;
+
 use env::Env;
 use error::*;
 use forms;
 use types::*;
 use util::*;
 
+// What's left of evaluating an expression: either its value, or an expression in
+// tail position to evaluate in its place. Tail calls come back out to the loop in
+// `Tail::run` rather than going deeper, so they don't grow the Rust stack.
+pub enum Tail {
+    Value(Expr),
+    Eval(Expr, Env),
+    // Go round the enclosing loop again with these values
+    Recur(Vec<Expr>),
+}
+
+impl Tail {
+    pub fn run(self) -> Result<Expr> {
+        let mut tail = self;
+        loop {
+            tail = match tail {
+                Tail::Value(value) => return Ok(value),
+                Tail::Eval(expr, env) => expr.eval_tail(env)?,
+                Tail::Recur(_) => bail!("#[recur] must be in tail position of a loop"),
+            };
+        }
+    }
+}
+
 impl Expr {
     pub fn eval(&self, env: Env) -> Result<Expr> {
+        self.eval_tail(env)?.run()
+    }
+
+    pub(crate) fn eval_tail(&self, env: Env) -> Result<Tail> {
+        match *self {
+            Expr::List(ref lst) => lst.eval_tail(env),
+            _ => self.eval_value(env).map(Tail::Value),
+        }
+    }
+
+    // Anything but a list, which evaluates to a value straight away
+    fn eval_value(&self, env: Env) -> Result<Expr> {
         match *self {
-            Expr::List(ref lst) => lst.eval(env),
+            Expr::Vector(ref vec) => vec.eval(env).map(Expr::Vector),
+            Expr::Map(ref map) => map.eval(env).map(Expr::Map),
+            Expr::Set(ref set) => set.eval(env).map(Expr::Set),
             Expr::Sym(ref symbol) => {
-                env.lookup(&symbol.0).ok_or_else(||
-                    format!("undefined symbol: {}", symbol.0).into()
-                )
+                env.lookup(&symbol.qualified_name()).ok_or_else(|| {
+                    Error::from(format!("undefined symbol: {}", symbol)).at(symbol.span.as_ref())
+                })
             }
             _ => Ok(self.clone()),
         }
     }
 
     pub(crate) fn eval_all(exprs: &[Expr], env: Env) -> Result<Expr> {
+        Expr::eval_body(exprs, env)?.run()
+    }
+
+    // Evaluate all but the last expression, which is left in tail position
+    pub(crate) fn eval_body(exprs: &[Expr], env: Env) -> Result<Tail> {
         match exprs.split_last() {
             Some((last, rest)) => {
                 for expr in rest {
                     expr.eval(env.clone())?;
                 }
-                last.eval(env.clone())
+                Ok(Tail::Eval(last.clone(), env))
             }
-            None => Ok(Expr::Nil),
+            None => Ok(Tail::Value(Expr::Nil)),
         }
     }
 }
 
 impl List {
-    pub fn eval(&self, env: Env) -> Result<Expr> {
-        if let Some((first, rest)) = self.0.split_first() {
+    pub fn eval_tail(&self, env: Env) -> Result<Tail> {
+        // Errors without a location of their own happened somewhere in this call
+        self.eval_call(env).map_err(|err| err.at(self.span.as_ref()))
+    }
+
+    fn eval_call(&self, env: Env) -> Result<Tail> {
+        if let Some((first, rest)) = self.items.split_first() {
+            // (:key map default?) looks the keyword up in a map
+            if let Expr::Keyword(ref keyword) = *first {
+                let evaled_args = List::eval_args(rest, env)?;
+                return keyword.apply(&evaled_args).map(Tail::Value);
+            }
+
             let sym = first.sym().ok_or("expected function call")?;
 
             if forms::is_special_form(sym) {
-                forms::eval(sym, rest, env)
+                forms::eval(self, env)
             } else if let Ok(Expr::Func(ref func)) = first.eval(env.clone()) {
                 // Eval all arguments, returning if any errors
                 let evaled_args = List::eval_args(rest, env.clone())?;
                 func.apply(&evaled_args, env.clone())
             } else if let Ok(Expr::Macro(ref mac)) = first.eval(env.clone()) {
-                mac.apply(rest, env.clone())?.eval(env.clone())
+                Ok(Tail::Eval(mac.apply(rest, env.clone())?, env))
             } else {
                 Err(format!("could not find symbol {}", first).into())
             }
 
         } else {
-            Ok(Expr::Nil)
+            Ok(Tail::Value(Expr::Nil))
         }
     }
 
@@ -59,28 +114,83 @@ impl List {
     }
 }
 
+impl Vector {
+    pub fn eval(&self, env: Env) -> Result<Vector> {
+        let items = List::eval_args(&self.items, env)?;
+        Ok(Vector { items, span: self.span.clone(), meta: self.meta.clone() })
+    }
+}
+
+impl Map {
+    // Evaluate each key and value in the order they're written, e.g.
+    // {(+ 1 2) x} => {3 <value of x>}. Keys which turn out the same are an error.
+    pub fn eval(&self, env: Env) -> Result<Map> {
+        let mut map = Map::new();
+        for (key, value) in self.forms().iter() {
+            let key = key.eval(env.clone())?;
+            let value = value.eval(env.clone())?;
+            let key = Key::try_from(&key).map_err(|err| err.at(self.span.as_ref()))?;
+            if map.insert(key.clone(), value).is_some() {
+                let err = Error::from(format!("duplicate key in map literal: {}", key));
+                return Err(err.at(self.span.as_ref()));
+            }
+        }
+        map.span = self.span.clone();
+        map.meta = self.meta.clone();
+        Ok(map)
+    }
+}
+
+impl Set {
+    pub fn eval(&self, env: Env) -> Result<Set> {
+        let mut set = Set::new();
+        for item in self.iter() {
+            let item = item.to_expr().eval(env.clone())?;
+            set.insert(Key::try_from(&item).map_err(|err| err.at(self.span.as_ref()))?);
+        }
+        set.span = self.span.clone();
+        set.meta = self.meta.clone();
+        Ok(set)
+    }
+}
+
 impl Function {
+    // Bind the arguments, leaving the last expression of the body for the caller
+    // to evaluate
     #[cfg_attr(rustfmt, rustfmt_skip)]
-    pub fn apply(&self, args: &[Expr], call_env: Env) -> Result<Expr> {
+    pub fn apply(&self, args: &[Expr], call_env: Env) -> Result<Tail> {
         match *self {
-            Function::Builtin { ref func, .. } => (func)(args, call_env),
-            Function::User { ref name, ref params, ref body, ref env } => {
+            Function::Builtin { ref func, .. } => (func)(args, call_env).map(Tail::Value),
+            Function::User { ref name, ref clauses, ref env, .. } => {
                 let name = if let &Some(ref n) = name { n.as_str() } else { "fn" };
-                ensure_args(name, args, params.len())?;
-                if args.len() != params.len() {
-                    return Err(format!("fn expected {} args", params.len()).into());
-                }
+
+                // With only one clause, binding its parameters says what's wrong with the args
+                let clause = match clauses.len() {
+                    1 => &clauses[0],
+                    _ => Function::clause(clauses, args.len()).ok_or_else(|| {
+                        format!("#[{}] expected {} args", name, Function::arities(clauses))
+                    })?,
+                };
 
                 // Create new env with arguments, eval body with new env
-                let bound_params = params
-                    .iter()
-                    .map(|x| x.0.to_owned())
-                    .zip(args.to_owned())
-                    .collect();
-
-                let fn_env = Env::new(bound_params, Some(env.clone()));
-                Expr::eval_all(body, fn_env)
+                let fn_env = Env::new(HashMap::new(), Some(env.clone()));
+                clause.params.bind(name, args, &fn_env)?;
+                Expr::eval_body(&clause.body, fn_env)
+            }
+        }
+    }
+}
+
+impl Keyword {
+    pub fn apply(&self, args: &[Expr]) -> Result<Expr> {
+        ensure_range_args(&self.to_string(), args, 1, 2)?;
+        let default = args.get(1).cloned().unwrap_or(Expr::Nil);
+        match args[0] {
+            Expr::Map(ref map) => {
+                Ok(map.get(&Key::Keyword(self.clone())).cloned().unwrap_or(default))
             }
+            Expr::Nil => Ok(default),
+            _ => Err(format!("#[{}] expected map", self).into()),
         }
     }
 }
@@ -88,16 +198,10 @@ impl Function {
 impl Macro {
     pub fn apply(&self, args: &[Expr], env: Env) -> Result<Expr> {
         let name = if let Some(ref n) = self.name { n.as_str() } else { "macro" };
-        ensure_args(name, args, self.params.len())?;
 
         // Create new env with arguments, eval body with new env
-        let bound_params = self.params
-            .iter()
-            .map(|x| x.0.to_owned())
-            .zip(args.to_owned())
-            .collect();
-
-        let fn_env = Env::new(bound_params, Some(env));
+        let fn_env = Env::new(HashMap::new(), Some(env));
+        self.params.bind(name, args, &fn_env)?;
 
         Expr::eval_all(&self.body, fn_env)
     }
@@ -0,0 +1,370 @@
+// Canonical formatting for source files. Every form is laid out by the same
+// rules as pprint, keeping comments, the spelling of every token, and single
+// blank lines between top-level forms.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use cst::{self, Cst, Leaf, Node};
+use diagnostic::Diagnostic;
+use error::*;
+use pprint::{text, Doc, Printer, Shape, Tree};
+use stream::StringStream;
+use token::Token;
+
+// The width code is formatted to, whatever the terminal is
+const WIDTH: usize = 80;
+
+pub fn format_source(source: &str, file: Option<&str>) -> ::std::result::Result<String, Diagnostic> {
+    let cst = cst::parse_cst(StringStream::at(source, file.map(Arc::from), 1))?;
+    Ok(Formatter { printer: Printer::new(WIDTH) }.format(cst))
+}
+
+// Format files in place, or with `check` only report those which aren't formatted.
+// Returns whether every file was already formatted.
+pub fn format_files(paths: &[&str], check: bool) -> Result<bool> {
+    let mut formatted = true;
+    for &path in paths {
+        let mut source = String::new();
+        fs::File::open(path)?.read_to_string(&mut source)?;
+        let output = format_source(&source, Some(path)).map_err(|err| ErrorKind::Syntax(vec![err]))?;
+
+        if output != source {
+            formatted = false;
+            if check {
+                println!("{}: not formatted", path);
+            } else {
+                fs::File::create(path)?.write_all(output.as_bytes())?;
+            }
+        }
+    }
+    Ok(formatted)
+}
+
+struct Formatter {
+    printer: Printer,
+}
+
+impl Formatter {
+    fn format(&self, cst: Cst) -> String {
+        // Top-level comments and forms each start a new line
+        fn start_line(out: &mut String, blank: &mut bool) {
+            if !out.is_empty() {
+                out.push('\n');
+                if *blank {
+                    out.push('\n');
+                }
+            }
+            *blank = false;
+        }
+
+        let mut out = String::new();
+        let mut blank = false;
+
+        let mut nodes: Vec<_> = cst.nodes.into_iter().map(Some).collect();
+        nodes.push(None);
+        for node in nodes {
+            let trivia = match node {
+                Some(ref node) => first_leaf(node).trivia.clone(),
+                None => cst.trailing.clone(),
+            };
+            let (trailing, items) = split_trivia(&trivia);
+
+            // A comment on the same line as the last form stays there
+            if let Some(comment) = trailing {
+                if out.is_empty() {
+                    out.push_str(&comment);
+                } else {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+            }
+            for item in items {
+                match item {
+                    Trivia::Blank => blank = true,
+                    Trivia::Comment(comment) => {
+                        start_line(&mut out, &mut blank);
+                        out.push_str(&comment);
+                    }
+                }
+            }
+
+            if let Some(node) = node {
+                start_line(&mut out, &mut blank);
+                out.push_str(&self.printer.render(self.tree(node, Vec::new())));
+            }
+        }
+
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+
+    // A tree for a node whose first leaf's trivia has already been taken, with the
+    // comments to print on the lines before it
+    fn tree(&self, node: Node, comments: Vec<Doc>) -> Tree {
+        let tree = match node {
+            Node::Atom(leaf) => {
+                let symbol = match leaf.token {
+                    Token::Symbol(ref name) if name == "/" || !name.contains('/') => Some(name.clone()),
+                    _ => None,
+                };
+                Tree::Atom(text(leaf.text), symbol)
+            }
+            Node::Prefix(leaf, children) => {
+                let mut prefix = text(leaf.text);
+                let trees = self.children(&mut prefix, children);
+                Tree::Prefix(prefix, trees)
+            }
+            Node::Delimited(open, children, close) => {
+                let shape = match open.token {
+                    Token::LParen | Token::AnonFn => Shape::Code,
+                    Token::LBrace => Shape::Pairs,
+                    _ => Shape::Data,
+                };
+                let mut open_doc = text(open.text);
+                let mut trees = self.children(&mut open_doc, children);
+
+                // Comments before the closer go on lines of their own, as if they were items
+                let (trailing, items) = split_trivia(&close.trivia);
+                attach(trailing, &mut open_doc, &mut trees);
+                let comments = comment_docs(items);
+                if !comments.is_empty() {
+                    let empty = Tree::Atom(Doc::Concat(Vec::new()), None);
+                    trees.push(Tree::Commented(Doc::Concat(comments), Box::new(empty), Doc::Concat(Vec::new())));
+                }
+                Tree::Form(open_doc, trees, text(close.text), shape)
+            }
+        };
+
+        if comments.is_empty() {
+            tree
+        } else {
+            Tree::Commented(Doc::Concat(comments), Box::new(tree), Doc::Concat(Vec::new()))
+        }
+    }
+
+    fn children(&self, first: &mut Doc, children: Vec<Node>) -> Vec<Tree> {
+        let mut trees = Vec::new();
+        for mut child in children {
+            let trivia = ::std::mem::take(&mut first_leaf_mut(&mut child).trivia);
+            let (trailing, items) = split_trivia(&trivia);
+            attach(trailing, first, &mut trees);
+            trees.push(self.tree(child, comment_docs(items)));
+        }
+        trees
+    }
+}
+
+// Put a comment at the end of the line it was on, after the last tree or `first` if there isn't one
+fn attach(trailing: Option<String>, first: &mut Doc, trees: &mut [Tree]) {
+    if let Some(comment) = trailing {
+        let doc = Doc::Concat(vec![text(format!(" {}", comment)), Doc::HardLine]);
+        match trees.last_mut() {
+            Some(tree) => tree.push(doc),
+            None => first.push(doc),
+        }
+    }
+}
+
+// Each comment on a line of its own. Blank lines only matter between top-level forms.
+fn comment_docs(items: Vec<Trivia>) -> Vec<Doc> {
+    items.into_iter()
+        .filter_map(|item| match item {
+            Trivia::Comment(comment) => Some(vec![text(comment), Doc::HardLine]),
+            Trivia::Blank => None,
+        })
+        .flatten()
+        .collect()
+}
+
+fn first_leaf(node: &Node) -> &Leaf {
+    match *node {
+        Node::Atom(ref leaf) | Node::Prefix(ref leaf, _) | Node::Delimited(ref leaf, _, _) => leaf,
+    }
+}
+
+fn first_leaf_mut(node: &mut Node) -> &mut Leaf {
+    match *node {
+        Node::Atom(ref mut leaf) | Node::Prefix(ref mut leaf, _) | Node::Delimited(ref mut leaf, _, _) => leaf,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Trivia {
+    Comment(String),
+    // One or more blank lines
+    Blank,
+}
+
+// The comments in the whitespace before a token, and one which carries on the
+// line before, if there is one
+fn split_trivia(trivia: &str) -> (Option<String>, Vec<Trivia>) {
+    let mut trailing = None;
+    let mut items = Vec::new();
+    let mut newlines = 0;
+    let mut seen_newline = false;
+    let mut rest = trivia;
+
+    while let Some(c) = rest.chars().next() {
+        let len = if c == ';' {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("#|") {
+            block_comment_len(rest)
+        } else {
+            if c == '\n' {
+                newlines += 1;
+                seen_newline = true;
+            }
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+
+        let comment = rest[..len].trim_end().to_owned();
+        rest = &rest[len..];
+        if !seen_newline && trailing.is_none() && items.is_empty() {
+            trailing = Some(comment);
+        } else {
+            if newlines > 1 {
+                items.push(Trivia::Blank);
+            }
+            items.push(Trivia::Comment(comment));
+        }
+        newlines = 0;
+    }
+
+    if newlines > 1 {
+        items.push(Trivia::Blank);
+    }
+    (trailing, items)
+}
+
+// The length of a #| block comment |#, which may nest
+fn block_comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("#|") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("|#") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                break;
+            }
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(source, None).unwrap()
+    }
+
+    // Pairs of source and how it should be formatted
+    const CORPUS: &[(&str, &str)] = &[
+        ("", ""),
+        ("  \n\n ", ""),
+        ("(+   1\n   2)", "(+ 1 2)\n"),
+        (
+            "; header\n\n\n(def x 0xFF)   ; hex\n(def y\n\n  #\"C:\\raw\")\n\n\n\n(def z 1)\n; the end",
+            "; header\n\n(def x 0xFF) ; hex\n(def y #\"C:\\raw\")\n\n(def z 1)\n; the end\n",
+        ),
+        (
+            "(defn-like add [a b]\n      (+ a b))",
+            "(defn-like add [a b] (+ a b))\n",
+        ),
+        (
+            "(def f (fn f [x y] (let [a (+ x 1) b (* y 2)] (if (< a b) (list a b) (list b a)))))",
+            "(def f\n  (fn f [x y] (let [a (+ x 1) b (* y 2)] (if (< a b) (list a b) (list b a)))))\n",
+        ),
+        (
+            "(def f (fn [x] (let [first-value (compute x) second-value (compute first-value)] (if (< first-value second-value) first-value second-value))))",
+            "(def f
+  (fn [x]
+    (let [first-value (compute x) second-value (compute first-value)]
+      (if (< first-value second-value) first-value second-value))))\n",
+        ),
+        (
+            "(let [x 1 ; one\n      y 2]\n  ;; add them\n  (+ x y) ; sum\n  )",
+            "(let [x 1 ; one
+      y 2]
+  ;; add them
+  (+ x y) ; sum
+  )\n",
+        ),
+        (
+            "{:a 1 :b [1 2 3] #| why |# :c \"str\"} #{3 1 2} '(quoted  list) `(a ~b ~@c) #_  (ignored) ^:private  x",
+            "{:a 1
+ :b [1 2 3] #| why |#
+ :c \"str\"}
+#{3 1 2}
+'(quoted list)
+`(a ~b ~@c)
+#_(ignored)
+^:private x\n",
+        ),
+        (
+            "#(+ %1\n %2) [\\a \\space \"two\nlines\" 1e3 ns/f]",
+            "#(+ %1 %2)\n[\\a \\space \"two\nlines\" 1e3 ns/f]\n",
+        ),
+        // Comments inside a form are indented like its body
+        ("(foo ; a\n ; b\n )", "(foo ; a\n  ; b\n  )\n"),
+        ("(foo\n ; x\n a b)", "(foo\n  ; x\n  a\n  b)\n"),
+        ("(a (foo ; x\n b) c)", "(a (foo ; x\n     b)\n   c)\n"),
+        // Bindings stay in pairs with a comment after them
+        ("(let [a 1 ; one\n b 2] ; after\n a)", "(let [a 1 ; one\n      b 2] ; after\n  a)\n"),
+        (
+            "(some-long-function-name {:first-key \"first value\" :second-key \"second value\"} [1 2 3])",
+            "(some-long-function-name {:first-key \"first value\" :second-key \"second value\"}
+                         [1 2 3])\n",
+        ),
+    ];
+
+    #[test]
+    fn corpus() {
+        for &(source, expected) in CORPUS {
+            assert_eq!(expected, format(source), "formatting {:?}", source);
+        }
+    }
+
+    #[test]
+    fn idempotent() {
+        for &(source, expected) in CORPUS {
+            assert_eq!(expected, format(expected), "reformatting {:?}", source);
+        }
+    }
+
+    // Only whitespace and comment placement change, so the code reads the same
+    #[test]
+    fn same_code() {
+        for &(source, expected) in CORPUS {
+            let before = cst::parse_cst(StringStream::new(source)).unwrap().lower().unwrap();
+            let after = cst::parse_cst(StringStream::new(expected)).unwrap().lower().unwrap();
+            assert_eq!(before, after);
+        }
+    }
+
+    #[test]
+    fn trivia() {
+        assert_eq!((None, vec![]), split_trivia(" \n "));
+        assert_eq!(
+            (Some("; same line".into()), vec![Trivia::Blank, Trivia::Comment("#| a\n |#".into())]),
+            split_trivia(" ; same line  \n\n #| a\n |#\n")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("1:1: unclosed `(`: expected `)`, found end of input", format_source("(a", None).unwrap_err().to_string());
+    }
+}
@@ -1,21 +1,28 @@
 use std::collections::HashMap;
-use itertools::Itertools;
 
 use env::Env;
 use error::*;
-use types::{Expr, Function, Macro, Symbol, Lambda};
+use eval::Tail;
+use types::{Clause, Expr, Function, Macro, Map, Key, List, Params, Pattern, Set, Vector, Symbol};
 use util::*;
 
+// A special form, which gets its arguments unevaluated and may leave an
+// expression in tail position for the caller to evaluate
+type Form = fn(&[Expr], Env) -> Result<Tail>;
+
 lazy_static! {
-    static ref SPECIAL_FORMS: HashMap<&'static str, Lambda> = {
-        let forms: Vec<(&'static str, Lambda)> = vec![
+    static ref SPECIAL_FORMS: HashMap<&'static str, Form> = {
+        let forms: Vec<(&'static str, Form)> = vec![
             ("def", def_form),
             ("if",  if_form),
             ("let", let_form),
+            ("loop", loop_form),
+            ("recur", recur_form),
             ("do",  do_form),
             ("fn",  fn_form),
             ("macro", macro_form),
             ("quote", quote_form),
+            ("quasiquote", quasiquote_form),
             ("and", and_form),
             ("or", or_form),
         ];
@@ -24,118 +31,495 @@ lazy_static! {
 }
 
 pub fn is_special_form(form: &Symbol) -> bool {
-    SPECIAL_FORMS.contains_key(form.0.as_str())
+    form.ns.is_none() && SPECIAL_FORMS.contains_key(form.name.as_str())
 }
 
-pub fn eval(form: &Symbol, args: &[Expr], env: Env) -> Result<Expr> {
+// Evaluate a list whose head is a special form
+pub fn eval(list: &List, env: Env) -> Result<Tail> {
+    let (head, args) = list.items.split_first().ok_or("expected special form")?;
+    let form = head.sym().ok_or("expected special form")?;
     debug_assert!(is_special_form(form));
-    (SPECIAL_FORMS.get(form.0.as_str()))
-        .ok_or_else(|| format!("{} form not found", form.0).into())
+    // Recurs in a fn or loop are checked before it first runs, and it needn't be again
+    if (form.name == "fn" || form.name == "loop") && !list.recur_checked.get() {
+        check_recur(list, false, None, &env)?;
+    }
+    (SPECIAL_FORMS.get(form.name.as_str()))
+        .ok_or_else(|| format!("{} form not found", form).into())
         .and_then(|f| (f)(args, env))
 }
 
 fn def_impl(args: &[Expr], env: Env) -> Result<Expr> {
     let sym = ensure_sym("def", &args[0])?;
-    args[1].eval(env.clone())
-        .map(|expr| env.define(&sym.0, expr))
-        .map(Expr::from)
+    let mut value = args[1].eval(env.clone())?;
+    // (def ^{:doc "..."} name init) gives the value the name's metadata
+    if let Some(ref meta) = sym.meta {
+        value = value.merge_meta(meta).unwrap_or(value);
+    }
+    Ok(Expr::from(env.define(&sym.qualified_name(), value)))
 }
 
 // (def symbol init)
-fn def_form(args: &[Expr], env: Env) -> Result<Expr> {
+fn def_form(args: &[Expr], env: Env) -> Result<Tail> {
     ensure_args("def", args, 2)?;
-    def_impl(&args, env)
+    def_impl(&args, env).map(Tail::Value)
 }
 
 // (if cond then else?)
-fn if_form(args: &[Expr], env: Env) -> Result<Expr> {
+fn if_form(args: &[Expr], env: Env) -> Result<Tail> {
     ensure_range_args("if", args, 2, 3)?;
-    if args[0].eval(env.clone())?.truthiness() {
-        args[1].eval(env.clone())
+    let branch = if args[0].eval(env.clone())?.truthiness() {
+        &args[1]
     } else {
-        args.get(2).unwrap_or(&Expr::Nil).eval(env.clone())
-    }
+        args.get(2).unwrap_or(&Expr::Nil)
+    };
+    Ok(Tail::Eval(branch.clone(), env))
 }
 
 // (do exprs*)
-fn do_form(args: &[Expr], env: Env) -> Result<Expr> {
-    Expr::eval_all(args, env.clone())
+fn do_form(args: &[Expr], env: Env) -> Result<Tail> {
+    Expr::eval_body(args, env)
 }
 
 // (let [bindings*] exprs*)
-fn let_form(args: &[Expr], env: Env) -> Result<Expr> {
+fn let_form(args: &[Expr], env: Env) -> Result<Tail> {
+    ensure_min_args("let", args, 1)?;
     let let_env = Env::new(HashMap::new(), Some(env));
-    let bindings = ensure_vector("let", &args[0])?;
 
-    for i in (0..bindings.0.len()).step(2) {
-        def_impl(&(bindings.0)[i..i+2], let_env.clone())?;
+    for (pattern, init) in bindings("let", &args[0])? {
+        let value = init.eval(let_env.clone())?;
+        pattern.bind("let", value, &let_env)?;
+    }
+
+    Expr::eval_body(&args[1..], let_env)
+}
+
+// The pattern and init form of each binding in a vector, e.g. [x 1 [y z] (list x 2)]
+fn bindings<'a>(form: &str, bindings: &'a Expr) -> Result<Vec<(Pattern, &'a Expr)>> {
+    let vector = ensure_vector(form, bindings)?;
+    if !vector.items.len().is_multiple_of(2) {
+        let err = Error::from(format!("#[{}] {}", form, odd_forms("bindings")));
+        return Err(err.at(vector.span.as_ref()));
+    }
+    vector.items.chunks(2)
+        .map(|pair| Ok((Pattern::parse(form, &pair[0])?, &pair[1])))
+        .collect()
+}
+
+// (loop [bindings*] exprs*), going round again with new values for the bindings
+// whenever the exprs end in (recur values*)
+fn loop_form(args: &[Expr], env: Env) -> Result<Tail> {
+    ensure_min_args("loop", args, 1)?;
+    let pairs = bindings("loop", &args[0])?;
+    let body = &args[1..];
+
+    let loop_env = Env::new(HashMap::new(), Some(env.clone()));
+    for (pattern, init) in &pairs {
+        let value = init.eval(loop_env.clone())?;
+        pattern.bind("loop", value, &loop_env)?;
     }
 
-    Expr::eval_all(&args[1..], let_env.clone())
+    let mut tail = Expr::eval_body(body, loop_env)?;
+    loop {
+        tail = match tail {
+            Tail::Recur(values) => {
+                ensure!(values.len() == pairs.len(), "#[recur] expected {} args, found {}", pairs.len(), values.len());
+                let recur_env = Env::new(HashMap::new(), Some(env.clone()));
+                for ((pattern, _), value) in pairs.iter().zip(values) {
+                    pattern.bind("recur", value, &recur_env)?;
+                }
+                Expr::eval_body(body, recur_env)?
+            }
+            Tail::Eval(expr, tail_env) if within_loop(&expr, &tail_env) => expr.eval_tail(tail_env)?,
+            // A call can't recur to this loop, so it's left to the caller like any other
+            tail => return Ok(tail),
+        };
+    }
+}
+
+// Whether an expression in tail position of a loop might still recur to it, i.e.
+// it's a special form or a macro rather than a call
+fn within_loop(expr: &Expr, env: &Env) -> bool {
+    match expr.list().and_then(|list| list.items.first()).and_then(Expr::sym) {
+        Some(sym) => is_special_form(sym) || matches!(env.lookup(&sym.qualified_name()), Some(Expr::Macro(_))),
+        None => false,
+    }
+}
+
+// (recur exprs*)
+fn recur_form(args: &[Expr], env: Env) -> Result<Tail> {
+    let values = args.iter().map(|arg| arg.eval(env.clone())).collect::<Result<_>>()?;
+    Ok(Tail::Recur(values))
+}
+
+// Check that every recur in a form is in tail position of a loop, with a value for
+// each of the loop's bindings, before any of it runs. Macros aren't expanded until
+// they run, so a recur they produce is checked then.
+fn check_recur_form(form: &Expr, tail: bool, bindings: Option<usize>, env: &Env) -> Result<()> {
+    match *form {
+        Expr::List(ref list) => check_recur(list, tail, bindings, env),
+        Expr::Vector(ref vec) => check_recur_all(&vec.items, bindings, env),
+        Expr::Map(ref map) => map.forms().iter().try_for_each(|(key, value)| {
+            check_recur_form(key, false, bindings, env)?;
+            check_recur_form(value, false, bindings, env)
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn check_recur(list: &List, tail: bool, bindings: Option<usize>, env: &Env) -> Result<()> {
+    let (head, args) = match list.items.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    let form = head.sym().filter(|sym| is_special_form(sym)).map(|sym| sym.name.as_str());
+    // What's inside a fn or loop doesn't depend on where it is, so each is only checked once
+    let self_contained = form == Some("fn") || form == Some("loop");
+    if self_contained && list.recur_checked.get() {
+        return Ok(());
+    }
+    let result = match form {
+        Some("recur") => match bindings {
+            Some(count) if tail && args.len() != count => {
+                Err(format!("#[recur] expected {} args, found {}", count, args.len()).into())
+            }
+            Some(_) if tail => check_recur_all(args, bindings, env),
+            _ => Err("#[recur] must be in tail position of a loop".into()),
+        },
+        Some("quote") | Some("quasiquote") => Ok(()),
+        Some("if") => args.split_first().map_or(Ok(()), |(cond, branches)| {
+            check_recur_form(cond, false, bindings, env)?;
+            branches.iter().try_for_each(|branch| check_recur_form(branch, tail, bindings, env))
+        }),
+        Some("do") | Some("and") | Some("or") if tail => check_recur_body(args, bindings, env),
+        Some("let") if tail => args.split_first().map_or(Ok(()), |(vector, body)| {
+            check_recur_form(vector, false, bindings, env)?;
+            check_recur_body(body, bindings, env)
+        }),
+        // The body of an inner loop recurs to that loop
+        Some("loop") => args.split_first().map_or(Ok(()), |(vector, body)| {
+            check_recur_form(vector, false, bindings, env)?;
+            let count = vector.vector().map_or(0, |vector| vector.items.len() / 2);
+            check_recur_body(body, Some(count), env)
+        }),
+        // A function's body is on its own, outside any loop
+        Some("fn") | Some("macro") => check_recur_all(args, None, env),
+        Some(_) => check_recur_all(args, bindings, env),
+        None => match head.sym().and_then(|sym| env.lookup(&sym.qualified_name())) {
+            Some(Expr::Macro(_)) => Ok(()),
+            _ => check_recur_all(&list.items, bindings, env),
+        },
+    };
+    if self_contained && result.is_ok() {
+        list.recur_checked.set(true);
+    }
+    result.map_err(|err| err.at(list.span.as_ref()))
+}
+
+// Forms in a body, the last of which is in tail position
+fn check_recur_body(body: &[Expr], bindings: Option<usize>, env: &Env) -> Result<()> {
+    match body.split_last() {
+        Some((last, rest)) => {
+            check_recur_all(rest, bindings, env)?;
+            check_recur_form(last, true, bindings, env)
+        }
+        None => Ok(()),
+    }
+}
+
+// Forms none of which are in tail position
+fn check_recur_all(forms: &[Expr], bindings: Option<usize>, env: &Env) -> Result<()> {
+    forms.iter().try_for_each(|form| check_recur_form(form, false, bindings, env))
 }
 
 // (quote form)
-fn quote_form(args: &[Expr], _env: Env) -> Result<Expr> {
+fn quote_form(args: &[Expr], _env: Env) -> Result<Tail> {
     ensure_args("quote", args, 1)?;
-    Ok(args[0].clone())
+    Ok(Tail::Value(args[0].clone()))
 }
 
-// (fn name? [params* ] exprs*)
-fn fn_form(args: &[Expr], env: Env) -> Result<Expr> {
-    ensure_min_args("fn", args, 2)?;
-    let name = args[0].sym().cloned().map(|n| n.0);
-    let raw_params = if name.is_some() { &args[1] } else { &args[0] };
-    let params = ensure_vector("fn", raw_params)?
-        .0.iter()
-        .map(|x| ensure_sym("fn", x).map(|x| x.clone()))
-        .collect::<Result<Vec<_>>>()?;
-    let body = if name.is_some() { args[2..].to_vec() } else { args[1..].to_vec() };
-    Ok(Expr::from(Function::User { name, params, body, env: env.clone() }))
+// (quasiquote form)
+fn quasiquote_form(args: &[Expr], env: Env) -> Result<Tail> {
+    ensure_args("quasiquote", args, 1)?;
+    if unquoted("unquote-splicing", &args[0]).is_some() {
+        bail!("#[quasiquote] unquote-splicing must be inside a list or vector");
+    }
+    quasiquote(&args[0], 1, env).map(Tail::Value)
+}
+
+// The argument of (name arg) if the form is that call
+fn unquoted<'a>(name: &str, form: &'a Expr) -> Option<&'a Expr> {
+    form.list()
+        .filter(|list| list.items.len() == 2)
+        .filter(|list| list.items[0].sym().is_some_and(|sym| sym.ns.is_none() && sym.name == name))
+        .map(|list| &list.items[1])
+}
+
+// Rebuild (name arg) around a new argument, keeping its spans
+fn requote(form: &Expr, arg: Expr) -> Expr {
+    let list = form.list().expect("requoted form is a list");
+    Expr::List(List { span: list.span.clone(), meta: list.meta.clone(), ..List::new(vec![list.items[0].clone(), arg]) })
+}
+
+// Expand a quasiquoted form, evaluating anything unquoted at this depth.
+// Each nested quasiquote goes one level deeper and each unquote comes back out.
+fn quasiquote(form: &Expr, depth: usize, env: Env) -> Result<Expr> {
+    if let Some(arg) = unquoted("unquote", form) {
+        return if depth == 1 {
+            arg.eval(env)
+        } else {
+            Ok(requote(form, quasiquote(arg, depth - 1, env)?))
+        };
+    }
+
+    if let Some(arg) = unquoted("quasiquote", form) {
+        return Ok(requote(form, quasiquote(arg, depth + 1, env)?));
+    }
+
+    match *form {
+        Expr::List(ref list) => {
+            let items = quasiquote_items(&list.items, depth, env)?;
+            Ok(Expr::List(List { span: list.span.clone(), meta: list.meta.clone(), ..List::new(items) }))
+        }
+        Expr::Vector(ref vec) => {
+            let items = quasiquote_items(&vec.items, depth, env)?;
+            Ok(Expr::Vector(Vector { items, span: vec.span.clone(), meta: vec.meta.clone() }))
+        }
+        Expr::Map(ref map) => {
+            let mut pairs = Vec::new();
+            for (key, value) in map.forms().iter() {
+                pairs.push((quasiquote(key, depth, env.clone())?, quasiquote(value, depth, env.clone())?));
+            }
+            let mut result = Map::from_forms(pairs).map_err(|err| err.at(map.span.as_ref()))?;
+            result.span = map.span.clone();
+            result.meta = map.meta.clone();
+            Ok(Expr::Map(result))
+        }
+        Expr::Set(ref set) => {
+            let mut result = Set::new();
+            result.span = set.span.clone();
+            result.meta = set.meta.clone();
+            for item in set.iter() {
+                result.insert(Key::try_from(&quasiquote(&item.to_expr(), depth, env.clone())?)?);
+            }
+            Ok(Expr::Set(result))
+        }
+        _ => Ok(form.clone()),
+    }
 }
 
-// (macro name? [params* ] exprs*)
-fn macro_form(args: &[Expr], _env: Env) -> Result<Expr> {
+// Expand the items of a list or vector, splicing in any (unquote-splicing seq)
+fn quasiquote_items(items: &[Expr], depth: usize, env: Env) -> Result<Vec<Expr>> {
+    let mut result = Vec::with_capacity(items.len());
+    for item in items {
+        match unquoted("unquote-splicing", item) {
+            Some(arg) if depth == 1 => match arg.eval(env.clone())? {
+                Expr::List(list) => result.extend(list.items),
+                Expr::Vector(vec) => result.extend(vec.items),
+                Expr::Nil => (),
+                _ => bail!("#[unquote-splicing] expected list or vector"),
+            },
+            Some(arg) => {
+                let arg = quasiquote(arg, depth - 1, env.clone())?;
+                result.push(requote(item, arg));
+            }
+            None => result.push(quasiquote(item, depth, env.clone())?),
+        }
+    }
+    Ok(result)
+}
+
+// (fn name? [params* (& rest)?] exprs*), where each param may be a pattern, or
+// (fn name? ([params*] exprs*)+) with a clause for each number of args
+fn fn_form(args: &[Expr], env: Env) -> Result<Tail> {
+    ensure_min_args("fn", args, 1)?;
+    let name = args[0].sym().map(|n| n.qualified_name().into_owned());
+    let rest = if name.is_some() { &args[1..] } else { args };
+
+    let clauses = match rest.first() {
+        Some(Expr::Vector(params)) => vec![fn_clause(params, &rest[1..])?],
+        Some(&Expr::List(_)) => rest.iter()
+            .map(|clause| {
+                let clause = ensure_list("fn", clause)?;
+                let (params, body) = clause.items.split_first().ok_or("#[fn] expected parameter vector")?;
+                ensure_vector("fn", params)
+                    .and_then(|params| fn_clause(params, body))
+                    .map_err(|err| err.at(clause.span.as_ref()))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => bail!("#[fn] expected parameter vector or clauses"),
+    };
+
+    let variadic = clauses.iter().filter(|clause| clause.is_variadic()).collect::<Vec<_>>();
+    ensure!(variadic.len() <= 1, "#[fn] can't have more than one variadic clause");
+    for (i, clause) in clauses.iter().enumerate() {
+        let (arity, max) = match clause.max_arity() {
+            Some(max) => (clause.arity(), max),
+            None => continue,
+        };
+        // Clauses with optional params take a range of counts, which mustn't overlap
+        for other in clauses[..i].iter().filter(|other| !other.is_variadic()) {
+            let shared = arity.max(other.arity());
+            ensure!(shared > max || !other.accepts(shared), "#[fn] can't have two clauses with {} params", shared);
+        }
+        ensure!(
+            variadic.first().is_none_or(|variadic| max <= variadic.arity()),
+            "#[fn] can't have a clause with more params than the variadic one"
+        );
+    }
+
+    // A named fn sees itself under its name, whatever the name means outside it
+    let fn_env = match name {
+        Some(_) => Env::new(HashMap::new(), Some(env)),
+        None => env,
+    };
+    let func = Expr::from(Function::User { name: name.clone(), clauses, env: fn_env.clone(), meta: None });
+    if let Some(name) = name {
+        fn_env.define(&name, func.clone());
+    }
+    Ok(Tail::Value(func))
+}
+
+fn fn_clause(params: &Vector, body: &[Expr]) -> Result<Clause> {
+    Ok(Clause { params: Params::parse("fn", params)?, body: body.to_vec() })
+}
+
+// (macro name? [params* (& rest)?] exprs*)
+fn macro_form(args: &[Expr], _env: Env) -> Result<Tail> {
     ensure_min_args("macro", args, 2)?;
-    let name = args[0].sym().cloned().map(|n| n.0);
+    let name = args[0].sym().map(|n| n.qualified_name().into_owned());
     let raw_params = if name.is_some() { &args[1] } else { &args[0] };
-    let params = ensure_vector("macro", raw_params)?
-        .0.iter()
-        .map(|x| ensure_sym("macro", x).map(|x| x.clone()))
-        .collect::<Result<Vec<_>>>()?;
+    let params = Params::parse("macro", ensure_vector("macro", raw_params)?)?;
     let body = if name.is_some() { args[2..].to_vec() } else { args[1..].to_vec() };
-    Ok(Expr::from(Macro::new(name, params, body)))
+    Ok(Tail::Value(Expr::from(Macro::new(name, params, body))))
 }
 
 // (and exprs*)
-fn and_form(args: &[Expr], env: Env) -> Result<Expr> {
+fn and_form(args: &[Expr], env: Env) -> Result<Tail> {
     if let Some((last, rest)) = args.split_last() {
         // Return on first untruthy value
         for arg in rest {
             let value = arg.eval(env.clone())?;
             if !value.truthiness() {
-                return Ok(value);
+                return Ok(Tail::Value(value));
             }
         }
-        last.eval(env.clone())
+        Ok(Tail::Eval(last.clone(), env))
     } else {
         // (and) returns #t
-        Ok(Expr::from(true))
+        Ok(Tail::Value(Expr::from(true)))
     }
 }
 
 // (or exprs*)
-fn or_form(args: &[Expr], env: Env) -> Result<Expr> {
+fn or_form(args: &[Expr], env: Env) -> Result<Tail> {
     if let Some((last, rest)) = args.split_last() {
         // Return on first truthy value
         for arg in rest {
             let value = arg.eval(env.clone())?;
             if value.truthiness() {
-                return Ok(value);
+                return Ok(Tail::Value(value));
             }
         }
-        last.eval(env.clone())
+        Ok(Tail::Eval(last.clone(), env))
     } else {
         //.clone() (or) returns #t
-        Ok(Expr::from(false))
+        Ok(Tail::Value(Expr::from(false)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use util::testing::*;
+
+    #[test]
+    fn quasiquote() {
+        let env = ops::env();
+        let printed = eval_printed("(def xs '(2 3))
+            `(1 ~(+ 1 1) ~@xs [~@xs 4] {:a ~(first xs)})
+            `(a `(b ~(c ~(first xs))))
+            (def unless (macro [c x] `(if ~c nil ~x)))
+            (unless #f :yes)", &env);
+        assert_eq!("(1 2 2 3 [2 3 4] {:a 2})", printed[1]);
+        assert_eq!("(a (quasiquote (b (unquote (c 2)))))", printed[2]);
+        assert_eq!(":yes", printed[4]);
+
+        let errors = eval_errors("`~@xs", &env);
+        assert!(errors[0].contains("unquote-splicing must be inside a list or vector"));
+    }
+
+    // Loops far deeper than the Rust stack, through every form with a tail position
+    #[test]
+    fn tail_calls() {
+        let env = ops::env();
+        let values = eval_all("(def count (fn [n acc] (if (= n 0) acc (count (- n 1) (+ acc 1)))))
+            (count 1000000 0)
+            (def even? (fn [n] (if (= n 0) #t (odd? (- n 1)))))
+            (def odd? (fn [n] (if (= n 0) #f (even? (- n 1)))))
+            (even? 100001)
+            (def down (fn [n] (and #t (or #f (do 1 (let [m (- n 1)] (if (< m 0) :done (down m))))))))
+            (down 10000)
+            (def with-last (macro [f x] `(~f ~x)))
+            (def again (fn [n] (if (= n 0) n (with-last again (- n 1)))))
+            (again 10000)", &env);
+        assert_eq!(Expr::from(1000000), values[1]);
+        assert_eq!(Expr::from(false), values[4]);
+        assert_eq!(":done", values[6].to_string());
+        assert_eq!(Expr::from(0), values[9]);
+
+        // A call which isn't in tail position still returns to its caller
+        let values = eval_all("(def sum (fn [n] (if (= n 0) 0 (+ n (sum (- n 1)))))) (sum 100)", &env);
+        assert_eq!(Expr::from(5050), values[1]);
+    }
+
+    #[test]
+    fn loop_recur() {
+        let env = ops::env();
+        let printed = eval_printed("(loop [i 0 acc []] (if (< i 5) (recur (+ i 1) (conj acc i)) acc))
+            (loop [i 0] (if (< i 100000) (recur (+ i 1)) i))
+            (loop [i 0] (and #t (or #f (let [j (+ i 1)] (if (< i 10) (do (recur j)) i)))))
+            (loop [x 1] (loop [y 2] (if (= y 2) (recur 3) [x y])))
+            (def unless (macro [c x] `(if ~c nil ~x)))
+            (loop [i 3 n 0] (unless (= i 0) (recur (- i 1) (+ n i))))
+            (def first-fn (loop [i 2 fs []] (if (= i 0) (first fs) (recur (- i 1) (conj fs (fn [] i))))))
+            (first-fn)", &env);
+        assert_eq!(vec!["[0 1 2 3 4]", "100000", "10", "[1 3]"], printed[..4].to_vec());
+        assert_eq!("nil", printed[5]);
+        // Each time round gets its own bindings
+        assert_eq!("2", printed[7]);
+
+        let errors = "(loop [i 0] (do (recur 1) 2))
+            (loop [i 0] (recur 1 2))
+            (loop [i 0] (fn [] (recur 1)))
+            (fn [x] (if x (recur #f) 1))
+            (loop [i 0] (+ 1 (unless #f (recur 2))))
+            (loop [i 1] (unless #f (recur)))
+            (recur 1)";
+        assert_eq!(
+            vec![
+                "test.tele:1:17: #[recur] must be in tail position of a loop",
+                "test.tele:2:25: #[recur] expected 1 args, found 2",
+                "test.tele:3:32: #[recur] must be in tail position of a loop",
+                "test.tele:4:27: #[recur] must be in tail position of a loop",
+                "test.tele:5:25: #[recur] must be in tail position of a loop",
+                "test.tele:6:13: #[recur] expected 1 args, found 0",
+                "#[recur] must be in tail position of a loop",
+            ],
+            eval_errors(errors, &env)
+        );
+
+        // A loop is checked the first time it runs, along with any fn or loop inside it,
+        // and only a form that passed is left alone after that
+        let exprs = read("(def count-to (fn [n] (loop [i 0] (if (< i n) (recur (+ i 1)) i))))");
+        exprs[0].eval(env.clone()).unwrap();
+        let inner = exprs[0].list().unwrap().items[2].list().unwrap();
+        assert!(inner.recur_checked.get());
+        assert!(inner.items[2].list().unwrap().recur_checked.get());
+        assert_eq!(vec!["3", "5"], eval_printed("(count-to 3) (count-to 5)", &env));
+        let bad = &read(errors)[0];
+        assert!(bad.eval(env.clone()).is_err());
+        assert!(!bad.list().unwrap().recur_checked.get());
     }
 }
@@ -1,72 +1,97 @@
+use std::cell::RefCell;
 use std::fs;
-use std::io;
-use std::io::prelude::*;
+use std::io::{self, BufRead, BufReader};
 
-use {lexer, parser, types};
+use types;
 use types::Expr;
 use error::*;
 use env::Env;
-use token::Token;
-use buffer::Readline;
-use stream::{StringStream, TokenStream};
+use buffer::{Console, Readline};
+use diagnostic::Diagnostic;
+use pprint::{self, Printer};
+use reader::Reader;
 
 pub fn file(path: &str, env: Env) -> Result<()> {
-    let file = fs::File::open(path)?;
-    let mut file_buf = io::BufReader::new(file);
-    loop {
-        let exprs = read(&mut file_buf)?;
-        eval(&exprs, env.clone())?;
+    let exprs = read_all(BufReader::new(fs::File::open(path)?), Some(path))?;
+    eval(&exprs, env).map(|_| ())
+}
+
+// Read a whole source, reporting every syntax error in it at once
+pub fn read_all<B: BufRead>(input: B, file: Option<&str>) -> Result<Vec<Expr>> {
+    let mut exprs = Vec::new();
+    let mut diagnostics = Vec::new();
+    for expr in Reader::new(input, file) {
+        match expr {
+            Ok(expr) => exprs.push(expr),
+            Err(Error(ErrorKind::Syntax(found), _)) => diagnostics.extend(found),
+            Err(Error(ErrorKind::Incomplete(span), _)) => {
+                diagnostics.push(Diagnostic::new(span, "incomplete form, expected more input"));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(exprs)
+    } else {
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        Err(ErrorKind::Syntax(diagnostics).into())
+    }
+}
+
+thread_local! {
+    // Standard input, shared so that a program read from it, or typed into the
+    // REPL, can (read) what follows
+    static STDIN: RefCell<Reader<Console>> =
+        RefCell::new(Reader::new(Console::Stdin(BufReader::new(io::stdin())), Some("<stdin>")));
+}
+
+// The next form from standard input, or None at the end of it
+pub fn read_stdin() -> Result<Option<Expr>> {
+    STDIN.with(|stdin| stdin.borrow_mut().read())
+}
+
+// Read and evaluate forms from standard input as they arrive
+pub fn stdin(env: Env) -> Result<()> {
+    while let Some(expr) = read_stdin()? {
+        expr.eval(env.clone())?;
     }
+    Ok(())
 }
 
 pub fn repl(env: Env) -> Result<i32> {
-    let mut rl = Readline::new("> ");
+    STDIN.with(|stdin| *stdin.borrow_mut() = Reader::new(Console::Readline(Readline::new("> ")), None));
     loop {
-        let exprs = match read(&mut rl) {
-            Ok(x) => x,
+        // Only borrowed while reading, so that what's evaluated can (read) too
+        let prompt = match STDIN.with(|stdin| stdin.borrow_mut().read_buffered()) {
+            Ok(Some(expr)) => {
+                match expr.eval(env.clone()) {
+                    Ok(val) => print(&val),
+                    Err(err) => match *err.kind() {
+                        ErrorKind::Exit(code) => return Ok(code),
+                        _ => println!("{}", err),
+                    },
+                }
+                continue;
+            }
+            Ok(None) => "> ",
+            // Prompt for the rest of an unfinished form
+            Err(ref err) if err.is_incomplete() => ".. ",
             Err(err) => {
                 println!("{}", err);
                 continue;
-            },
-        };
-        match eval(&exprs, env.clone()) {
-            Ok(val) => print(&val),
-            Err(err) => {
-                match *err.kind() {
-                    ErrorKind::Eof => return Ok(0),
-                    ErrorKind::Exit(code) => return Ok(code),
-                    _ => println!("{}", err),
-                }
             }
         };
-    }
-}
-
-fn read<B: BufRead>(reader: &mut B) -> Result<Vec<Expr>> {
-    let mut token_buf: Vec<Token> = Vec::with_capacity(128);
-    let mut expr_buf: Vec<Expr> = Vec::with_capacity(16);
-    let mut lines = reader.lines();
-
-    loop {
-        let line = match lines.next() {
-            Some(Ok(l)) => l,
-            Some(Err(err)) => return Err(err.into()),
-            None => return Err(ErrorKind::Eof.into()),
-        };
 
-        let (tokens, _) = lexer::lex(StringStream::new(&line))?;
-        token_buf.extend(tokens);
-
-        let (exprs, unparsed) = parser::parse(TokenStream::new(token_buf.drain(..)))?;
-
-        token_buf = unparsed.unwrap();
-        expr_buf.extend(exprs);
-
-        if token_buf.is_empty() {
-            break;
+        let more = STDIN.with(|stdin| {
+            let mut stdin = stdin.borrow_mut();
+            stdin.get_mut().set_prompt(prompt);
+            stdin.fill()
+        })?;
+        if !more {
+            return Ok(0);
         }
     }
-    Ok(expr_buf)
 }
 
 fn eval(exprs: &[Expr], env: Env) -> Result<Expr> {
@@ -80,8 +105,30 @@ fn eval(exprs: &[Expr], env: Env) -> Result<Expr> {
     }
 }
 
+// On one line if it fits in the terminal, or pretty printed over several if not
 fn print(value: &Expr) {
     if value != &types::Expr::Nil {
-        println!("{}", value);
+        let width = pprint::terminal_width();
+        let text = value.to_string();
+        if text.chars().count() > width {
+            println!("{}", Printer::new(width).print(value));
+        } else {
+            println!("{}", text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_all_errors() {
+        let err = read_all("(def x 1x)\n(def y [1 2)\n(+ x y)".as_bytes(), Some("test.tele")).unwrap_err();
+        assert_eq!(
+            "test.tele:1:8: unexpected `1x`\ntest.tele:2:8: unclosed `[`: expected `]`, found `)` at 2:12",
+            err.to_string()
+        );
+        assert_eq!(3, read_all("(def x 1)\n(def y [1 2])\n(+ x y)".as_bytes(), None).unwrap().len());
     }
 }
@@ -1,26 +1,124 @@
+use std::fmt;
+
 use combine::{Parser, Stream, ParseError, ParseResult};
-use combine::{between, many, many1, one_of, optional, parser, satisfy, satisfy_map, try};
-use combine::char::{digit, char, spaces};
+use combine::primitives::Error;
+use combine::{any, between, many, many1, none_of, not_followed_by, one_of, optional, parser,
+              position, satisfy, satisfy_map, sep_by1, skip_many, try};
+use combine::char::{char, hex_digit, space, string};
 
-use token::{Literal, Token};
+use diagnostic::Diagnostic;
+use span::Span;
+use token::{Literal, Token, CHAR_NAMES};
 use unicode_xid::UnicodeXID;
 
-pub fn lex<I>(input: I) -> Result<(Vec<Token>, I), ParseError<I>>
+pub type SpannedTokens = Vec<(Token, Span)>;
+
+#[cfg(test)]
+pub fn lex<I>(input: I) -> Result<(SpannedTokens, I), ParseError<I>>
 where
-    I: Stream<Item = char>,
+    I: Stream<Item = char, Position = Span>,
 {
-    between(spaces(),
-            spaces(),
-            many(spaces().with(parser(token))))
+    use combine::eof;
+
+    parser(whitespace)
+        .with(many(parser(spanned_token).skip(parser(whitespace))))
+        .skip(eof())
         .parse(input)
 }
 
+// Describe a token which failed to lex, and skip past it so that lexing can carry on
+pub fn recover<I>(err: &ParseError<I>, input: I) -> (Diagnostic, I)
+where
+    I: Stream<Item = char, Position = Span>,
+    I::Range: fmt::Display,
+{
+    let start = input.position();
+    let (text, rest) = skip_token(input);
+    // Prefer our own messages, like "unknown escape", to what combine expected
+    let explained = err.errors.iter().any(|e| matches!(*e, Error::Message(_) | Error::Other(_)));
+    let diagnostic = if explained {
+        Diagnostic::from_parse_error(err)
+    } else {
+        Diagnostic::new(start.to(&rest.position()), format!("unexpected `{}`", text))
+    };
+    (diagnostic, rest)
+}
+
+// Skip a bad token, up to the next whitespace or delimiter, or to the end of a
+// string so that its contents aren't lexed as code. Returns the skipped text.
+fn skip_token<I: Stream<Item = char>>(mut input: I) -> (String, I) {
+    let mut text = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    loop {
+        let mut next = input.clone();
+        let c = match next.uncons() {
+            Ok(c) => c,
+            Err(_) => return (text, input),
+        };
+
+        if in_string {
+            if c == '"' && !escaped {
+                in_string = false;
+            }
+            escaped = c == '\\' && !escaped;
+        } else if c == '"' && (text.is_empty() || text == "#") {
+            in_string = true;
+        } else if !text.is_empty() && (c.is_whitespace() || "()[]{}\";".contains(c)) {
+            return (text, input);
+        }
+
+        text.push(c);
+        input = next;
+    }
+}
+
+// Whitespace and comments, which separate tokens but are otherwise ignored
+pub fn whitespace<I>(input: I) -> ParseResult<(), I>
+where
+    I: Stream<Item = char>,
+{
+    let line_comment = char(';').with(skip_many(satisfy(|c| c != '\n')));
+
+    skip_many(
+        space().map(|_| ())
+            .or(line_comment)
+            .or(parser(block_comment))
+    ).parse_stream(input)
+}
+
+// #| ... |#, which may be nested
+fn block_comment<I>(input: I) -> ParseResult<(), I>
+where
+    I: Stream<Item = char>,
+{
+    let body = try(parser(block_comment))
+        .or(try(char('|').skip(not_followed_by(char('#')))).map(|_| ()))
+        .or(try(char('#').skip(not_followed_by(char('|')))).map(|_| ()))
+        .or(none_of("|#".chars()).map(|_| ()));
+
+    try(string("#|"))
+        .with(skip_many(body))
+        .skip(string("|#"))
+        .parse_stream(input)
+}
+
+pub fn spanned_token<I>(input: I) -> ParseResult<(Token, Span), I>
+where
+    I: Stream<Item = char, Position = Span>,
+{
+    (position(), parser(token), position())
+        .map(|(start, token, end): (Span, _, Span)| (token, start.to(&end)))
+        .parse_stream(input)
+}
+
 fn token<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
 {
-    parser(symbol)
-        .or(parser(literal))
+    parser(literal)
+        .or(parser(symbol))
         .or(parser(punctuation))
         .parse_stream(input)
 }
@@ -29,95 +127,292 @@ fn literal<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
 {
-    let sign = optional(char('-'));
-    let digits = many1::<String, _>(digit());
-    let integer =
-    (
-        sign.clone(),
-        digits.clone().and_then(|s| s.parse::<i64>())
-    )
-        .map(|(sign, num)| if sign.is_some() { -num } else { num })
-        .map(Literal::from);
-
-    let float = sign.clone()
-        .and(
-            (digits.clone(), char('.'), digits.clone())
-                .map(|(prefix, _, suffix)| format!("{}.{}", prefix, suffix))
-                .and_then(|num| num.parse::<f64>()),
-        )
-        .map(|(sign, num)| if sign.is_some() { -num } else { num })
-        .map(Literal::from);
-
-    let num = try(float).or(try(integer));
-
-    let boolean = char('#')
+    let boolean = try(char('#')
         .with(satisfy_map(|c| match c {
             't' => Some(true),
             'f' => Some(false),
             _ => None,
-        }))
+        })))
         .map(Literal::from);
 
-    let escaped = char('\\').with(satisfy_map(|c| match c {
+    let non_quote = parser(escape).or(satisfy(|c| c != '"' && c != '\\'));
+
+    let string = between(char('"'), char('"'), many::<String, _>(non_quote)).map(Literal::from);
+
+    // #"C:\path" has no escapes
+    let raw_string = try(char('#').with(char('"')))
+        .with(many::<String, _>(satisfy(|c| c != '"')))
+        .skip(char('"'))
+        .map(Literal::from);
+
+    // \a, \space or \u00e9
+    let character = char('\\')
+        .with(any().and(many::<String, _>(satisfy(char::is_alphanumeric))))
+        .and_then(|(first, rest)| char_literal(first, &rest))
+        .map(Literal::from);
+
+    boolean
+        .or(parser(number))
+        .or(string)
+        .or(raw_string)
+        .or(character)
+        .map(Token::from)
+        .parse_stream(input)
+}
+
+// An escape sequence in a string: \n, \", \x41 or \u{1F600}
+fn escape<I>(input: I) -> ParseResult<char, I>
+where
+    I: Stream<Item = char>,
+{
+    let simple = satisfy_map(|c| match c {
         '\"' => Some('\"'),
         '\\' => Some('\\'),
         'n' => Some('\n'),
         'r' => Some('\r'),
         't' => Some('\t'),
+        '0' => Some('\0'),
         _ => None,
-    }));
+    });
 
-    let non_quote = try(escaped).or(satisfy(|c| c != '"'));
+    let byte = char('x')
+        .with((hex_digit(), hex_digit()))
+        .map(|(hi, lo)| format!("{}{}", hi, lo))
+        .and_then(|hex| code_point(&hex, format!("\\x{}", hex)));
 
-    let string = between(char('"'), char('"'), many::<String, _>(non_quote)).map(Literal::from);
+    let unicode = char('u')
+        .with(between(char('{'), char('}'), many1::<String, _>(hex_digit())))
+        .and_then(|hex| code_point(&hex, format!("\\u{{{}}}", hex)));
 
-    boolean
-        .or(num)
-        .or(string)
-        .map(Token::from)
+    let unknown = any().and_then(|c| {
+        Err(Error::Message(format!("unknown escape in string: \\{}", c).into()))
+    });
+
+    char('\\')
+        .with(simple.or(byte).or(unicode).or(unknown))
         .parse_stream(input)
 }
 
+fn code_point<T, R>(hex: &str, escape: String) -> Result<char, Error<T, R>> {
+    u32::from_str_radix(hex, 16).ok()
+        .and_then(::std::char::from_u32)
+        .ok_or_else(|| Error::Message(format!("invalid escape in string: {}", escape).into()))
+}
+
+// The digits of a number, optionally separated by underscores, e.g. 1_000_000
+fn digits<I>(radix: u32) -> impl Parser<Input = I, Output = String>
+where
+    I: Stream<Item = char>,
+{
+    let digit = satisfy(move |c: char| c.is_digit(radix));
+    sep_by1::<Vec<String>, _, _>(many1(digit), char('_')).map(|groups| groups.concat())
+}
+
+enum Number {
+    Int(String, u32),
+    Flt(String),
+    Inf,
+    NaN,
+}
+
+// Integers: 42, -7, +3, 1_000_000, 0xff, 0b1010, 0o17
+// Floats: 3.14, .5, -1e-9, 6.02E23, +inf.0, -inf.0, +nan.0
+fn number<I>(input: I) -> ParseResult<Literal, I>
+where
+    I: Stream<Item = char>,
+{
+    let sign = || one_of("+-".chars());
+
+    let special = string("inf.0").map(|_| Number::Inf)
+        .or(string("nan.0").map(|_| Number::NaN));
+
+    let radix = try(char('0').with(satisfy_map(|c| match c {
+        'x' | 'X' => Some(16),
+        'o' | 'O' => Some(8),
+        'b' | 'B' => Some(2),
+        _ => None,
+    })));
+    let radix_int = radix.then(|radix| digits(radix).map(move |digits| Number::Int(digits, radix)));
+
+    let exponent = || {
+        one_of("eE".chars())
+            .with((optional(sign()), digits(10)))
+            .map(|(sign, digits)| format!("e{}{}", sign.unwrap_or('+'), digits))
+    };
+    let fraction = || char('.').with(digits(10));
+
+    let decimal = (digits(10), optional(fraction()), optional(exponent()))
+        .map(|(int, frac, exp)| match (frac, exp) {
+            (None, None) => Number::Int(int, 10),
+            (frac, exp) => Number::Flt(format!(
+                "{}.{}{}",
+                int,
+                frac.unwrap_or_else(|| "0".into()),
+                exp.unwrap_or_default()
+            )),
+        });
+
+    let leading_dot = (fraction(), optional(exponent()))
+        .map(|(frac, exp)| Number::Flt(format!("0.{}{}", frac, exp.unwrap_or_default())));
+
+    // A number can't run straight into a symbol, e.g. 1st or 1.2.3
+    let end = not_followed_by(satisfy(|c| is_symbol_body(c) || c == '/'));
+
+    let signed_special = try((sign().map(Some), special));
+    let unsigned = try((optional(sign()), radix_int.or(decimal).or(leading_dot)).skip(end));
+
+    signed_special
+        .or(unsigned)
+        .and_then(|(sign, number)| number_literal(sign, number))
+        .parse_stream(input)
+}
+
+fn number_literal<T, R>(sign: Option<char>, number: Number) -> Result<Literal, Error<T, R>> {
+    let negative = sign == Some('-');
+    match number {
+        Number::Int(digits, radix) => {
+            let signed = format!("{}{}", if negative { "-" } else { "" }, digits);
+            i64::from_str_radix(&signed, radix)
+                .map(Literal::Int)
+                .map_err(|_| Error::Message(format!("integer out of range: {}", signed).into()))
+        }
+        Number::Flt(text) => {
+            let value = text.parse::<f64>()
+                .map_err(|err| Error::Message(format!("invalid float: {}", err).into()))?;
+            Ok(Literal::Flt(if negative { -value } else { value }))
+        }
+        Number::Inf if negative => Ok(Literal::Flt(f64::NEG_INFINITY)),
+        Number::Inf => Ok(Literal::Flt(f64::INFINITY)),
+        Number::NaN => Ok(Literal::Flt(f64::NAN)),
+    }
+}
+
+fn char_literal<T, R>(first: char, rest: &str) -> Result<char, Error<T, R>> {
+    if rest.is_empty() {
+        return Ok(first);
+    }
+
+    let name = format!("{}{}", first, rest);
+    if let Some(&(_, c)) = CHAR_NAMES.iter().find(|&&(named, _)| named == name) {
+        return Ok(c);
+    }
+
+    if first == 'u' && rest.len() == 4 {
+        if let Some(c) = u32::from_str_radix(rest, 16).ok().and_then(char::from_u32) {
+            return Ok(c);
+        }
+    }
+
+    Err(Error::Message(format!("unknown character: \\{}", name).into()))
+}
+
+// Symbols are a name, optionally qualified by a namespace:
+//
+//   symbol = "/" | name | name "/" name
+//   name   = start body*
+//   start  = XID_Start | one of _ + - * = < > ! ? % & $ .
+//   body   = start | XID_Continue
+//
+// So empty?, swap!, ->>, a.b and str/join are all single symbols, and a lone /
+// is division. Anything that reads as a number, like -1 or .5, is lexed as one.
+const SYMBOL_PUNCTUATION: &str = "_+-*=<>!?%&$.";
+
+fn is_symbol_start(c: char) -> bool {
+    UnicodeXID::is_xid_start(c) || SYMBOL_PUNCTUATION.contains(c)
+}
+
+fn is_symbol_body(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c) || SYMBOL_PUNCTUATION.contains(c)
+}
+
 fn symbol<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
 {
-    let punctuation = one_of("_+-*/=<>!".chars());
-    let start = satisfy(UnicodeXID::is_xid_start).or(punctuation.clone());
-    let body = satisfy(UnicodeXID::is_xid_continue).or(punctuation.clone());
+    let start = satisfy(|c| is_symbol_start(c) || c == '/');
+    let body = satisfy(|c| is_symbol_body(c) || c == '/');
     let rest = many::<String, _>(body);
-    start
+    let name = start
         .and(rest)
-        .map(|(f, mut r)| {
+        .map(|(f, mut r): (char, String)| {
             r.insert(0, f);
             r
         })
-        .map(Token::Symbol)
+        .and_then(qualified_name);
+
+    // :name is a keyword
+    optional(char(':'))
+        .and(name)
+        .map(|(colon, name)| if colon.is_some() {
+            Token::Keyword(name)
+        } else {
+            Token::Symbol(name)
+        })
         .parse_stream(input)
 }
 
+// Check a run of symbol characters has at most one / between a namespace and a name
+fn qualified_name<T, R>(text: String) -> Result<String, Error<T, R>> {
+    let valid = match text.find('/') {
+        _ if text == "/" => true,
+        Some(i) => {
+            let (ns, name) = (&text[..i], &text[i + 1..]);
+            !ns.is_empty() && name.starts_with(is_symbol_start) && !name.contains('/')
+        }
+        None => true,
+    };
+
+    if valid {
+        Ok(text)
+    } else {
+        Err(Error::Message(format!("invalid symbol: {}", text).into()))
+    }
+}
+
 fn punctuation<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
 {
-    satisfy_map(|c| match c {
-        '(' => Some(Token::LParen),
-        ')' => Some(Token::RParen),
-        '[' => Some(Token::LBracket),
-        ']' => Some(Token::RBracket),
-        '\'' => Some(Token::Quote),
-        _ => None,
-    }).parse_stream(input)
+    let discard = try(string("#_")).map(|_| Token::Discard);
+    let anon_fn = try(string("#(")).map(|_| Token::AnonFn);
+    let set = try(string("#{")).map(|_| Token::Set);
+    let unquote_splicing = try(string("~@")).map(|_| Token::UnquoteSplicing);
+
+    discard
+        .or(anon_fn)
+        .or(set)
+        .or(unquote_splicing)
+        .or(satisfy_map(|c| match c {
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            '[' => Some(Token::LBracket),
+            ']' => Some(Token::RBracket),
+            '{' => Some(Token::LBrace),
+            '}' => Some(Token::RBrace),
+            '\'' => Some(Token::Quote),
+            '`' => Some(Token::Quasiquote),
+            '~' => Some(Token::Unquote),
+            '^' => Some(Token::Meta),
+            _ => None,
+        }))
+        .parse_stream(input)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use float_cmp::ApproxEqUlps;
+    use span::Position;
+    use stream::{self, StringStream};
+
+    fn lex_tokens(input: &str) -> Result<Vec<Token>, ParseError<StringStream>> {
+        lex(StringStream::new(input))
+            .map(|(tokens, _)| tokens.into_iter().map(|(token, _)| token).collect())
+    }
 
     #[test]
     fn empty() {
-        assert_eq!(Ok((vec![], "")), lex(""));
+        assert_eq!(Ok(vec![]), lex_tokens(""));
     }
 
     #[test]
@@ -139,6 +434,60 @@ mod test {
         };
     }
 
+    #[test]
+    fn number_forms() {
+        let int = |input| parser(literal).parse(input).map(|(token, _)| token);
+        assert_eq!(Ok(Token::from(255)), int("0xff"));
+        assert_eq!(Ok(Token::from(-10)), int("-0b1010"));
+        assert_eq!(Ok(Token::from(15)), int("0o17"));
+        assert_eq!(Ok(Token::from(1_000_000)), int("1_000_000"));
+        assert_eq!(Ok(Token::from(3)), int("+3"));
+        assert_eq!(Ok(Token::from(0.5)), int(".5"));
+        assert_eq!(Ok(Token::from(-0.5)), int("-.5"));
+        assert_eq!(Ok(Token::from(1e-9)), int("1e-9"));
+        assert_eq!(Ok(Token::from(1.5e3)), int("1.5E3"));
+        assert_eq!(Ok(Token::from(f64::INFINITY)), int("+inf.0"));
+        assert_eq!(Ok(Token::from(f64::NEG_INFINITY)), int("-inf.0"));
+        match int("+nan.0") {
+            Ok(Token::Literal(Literal::Flt(x))) => assert!(x.is_nan()),
+            other => panic!("+nan.0 lexed as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn int_bounds() {
+        assert_eq!(Ok(vec![Token::from(i64::MIN)]), lex_tokens("-9223372036854775808"));
+        assert_eq!(Ok(vec![Token::from(i64::MAX)]), lex_tokens("9223372036854775807"));
+
+        let err = lex_tokens("(+ 1\n   9223372036854775808)").unwrap_err();
+        assert_eq!(Position::new(2, 4), err.position.start);
+        assert!(err.to_string().contains("integer out of range: 9223372036854775808"));
+    }
+
+    #[test]
+    fn numbers_and_symbols() {
+        let sym = |s: &str| Token::Symbol(s.into());
+        assert_eq!(
+            Ok(vec![sym("-"), sym("+"), sym("->"), sym("-x"), Token::from(-1)]),
+            lex_tokens("- + -> -x -1")
+        );
+        assert_eq!(
+            Ok(vec![sym("empty?"), sym("swap!"), sym("->>"), sym("a.b"), sym("$x"), sym("&"), sym("%")]),
+            lex_tokens("empty? swap! ->> a.b $x & %")
+        );
+        assert_eq!(
+            Ok(vec![sym("/"), sym("str/join"), Token::Keyword("ns/k".into()), sym("a.b/c-d")]),
+            lex_tokens("/ str/join :ns/k a.b/c-d")
+        );
+        for invalid in &["a/b/c", "a/", "/a", "a//", "a/1"] {
+            assert!(lex_tokens(invalid).unwrap_err().to_string().contains("invalid symbol"), "{}", invalid);
+        }
+        assert!(lex_tokens("1st").is_err());
+        assert!(lex_tokens("1/2").is_err());
+        assert!(lex_tokens("1_").is_err());
+        assert!(lex_tokens("1.2.3").is_err());
+    }
+
     #[test]
     fn escape_chars() {
         assert_eq!(
@@ -163,6 +512,43 @@ mod test {
         );
     }
 
+    #[test]
+    fn char_literal() {
+        assert_eq!(Ok((Token::from('a'), "")), parser(literal).parse("\\a"));
+        assert_eq!(Ok((Token::from('('), "")), parser(literal).parse("\\("));
+        assert_eq!(Ok((Token::from(' '), "")), parser(literal).parse("\\space"));
+        assert_eq!(Ok((Token::from('\t'), "")), parser(literal).parse("\\tab"));
+        assert_eq!(Ok((Token::from('\n'), "")), parser(literal).parse("\\newline"));
+        assert_eq!(Ok((Token::from('é'), "")), parser(literal).parse("\\u00e9"));
+        assert!(parser(literal).parse("\\spaces").is_err());
+        assert_eq!(
+            Ok(vec![Token::LParen, Token::from('a'), Token::from(')'), Token::RParen]),
+            lex_tokens("(\\a \\))")
+        );
+    }
+
+    #[test]
+    fn string_escapes() {
+        let string = |input| parser(literal).parse(input).map(|(token, _)| token);
+        assert_eq!(Ok(Token::from("A\0")), string(r#""\x41\0""#));
+        assert_eq!(Ok(Token::from("é😀")), string(r#""\u{e9}\u{1F600}""#));
+        assert_eq!(Ok(Token::from(r"C:\path\n")), string(r#"#"C:\path\n""#));
+        assert_eq!(Ok(Token::from("two\nlines")), string("\"two\nlines\""));
+
+        let err = lex_tokens(r#"(f "ok\q")"#).unwrap_err();
+        assert_eq!(Position::new(1, 8), err.position.start);
+        assert!(err.to_string().contains(r"unknown escape in string: \q"));
+
+        let err = lex_tokens(r#""\u{110000}""#).unwrap_err();
+        assert!(err.to_string().contains(r"invalid escape in string: \u{110000}"));
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let err = lex_tokens("\"first line").unwrap_err();
+        assert!(stream::is_incomplete(&err));
+    }
+
     #[test]
     fn delimiters() {
         // Single-character tests
@@ -185,13 +571,23 @@ mod test {
             Ok((Token::RBracket, "")),
             parser(punctuation).parse("]")
         );
+
+        assert_eq!(
+            Ok((Token::LBrace, "")),
+            parser(punctuation).parse("{")
+        );
+
+        assert_eq!(
+            Ok((Token::RBrace, "")),
+            parser(punctuation).parse("}")
+        );
     }
 
     #[test]
     fn nested_lists() {
         assert_eq!(
-            Ok((vec![Token::LParen, Token::LParen, Token::RParen, Token::RParen],"")),
-            lex("(())")
+            Ok(vec![Token::LParen, Token::LParen, Token::RParen, Token::RParen]),
+            lex_tokens("(())")
         );
     }
 
@@ -204,8 +600,107 @@ mod test {
         let right = iter::repeat(Token::RParen).take(num_layers);
         let output = left.chain(right).collect::<Vec<_>>();
         assert_eq!(
-            Ok((output, "")),
-            lex(&*input)
+            Ok(output),
+            lex_tokens(&input)
+        );
+    }
+
+    #[test]
+    fn line_comments() {
+        assert_eq!(
+            Ok(vec![Token::LParen, Token::RParen]),
+            lex_tokens("; note\n( ; inner\n) ; trailing")
+        );
+    }
+
+    #[test]
+    fn block_comments() {
+        assert_eq!(
+            Ok(vec![Token::LParen, Token::RParen]),
+            lex_tokens("#| a\nb |# (#| x | # |#)")
+        );
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(
+            Ok(vec![Token::from(1)]),
+            lex_tokens("#| outer #| inner |# still outer |# 1")
+        );
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let err = lex_tokens("#| a #| b |#").unwrap_err();
+        assert!(stream::is_incomplete(&err));
+    }
+
+    #[test]
+    fn discard() {
+        assert_eq!(
+            Ok(vec![Token::Discard, Token::from(1), Token::from(2)]),
+            lex_tokens("#_1 2")
+        );
+    }
+
+    #[test]
+    fn quasiquote() {
+        assert_eq!(
+            Ok(vec![
+                Token::Quasiquote, Token::LParen, Token::Unquote, Token::Symbol("a".into()),
+                Token::UnquoteSplicing, Token::Symbol("b".into()), Token::RParen,
+            ]),
+            lex_tokens("`(~a ~@b)")
+        );
+    }
+
+    #[test]
+    fn anon_fn() {
+        assert_eq!(
+            Ok(vec![
+                Token::AnonFn, Token::Symbol("+".into()), Token::Symbol("%".into()),
+                Token::Symbol("%2".into()), Token::Symbol("%&".into()), Token::RParen,
+            ]),
+            lex_tokens("#(+ % %2 %&)")
+        );
+    }
+
+    #[test]
+    fn set() {
+        assert_eq!(
+            Ok(vec![Token::Set, Token::from(1), Token::RBrace, Token::LBrace, Token::RBrace]),
+            lex_tokens("#{1} {}")
+        );
+    }
+
+    #[test]
+    fn meta() {
+        assert_eq!(
+            Ok(vec![Token::Meta, Token::Keyword("dynamic".into()), Token::Symbol("x".into())]),
+            lex_tokens("^:dynamic x")
+        );
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(
+            Ok(vec![Token::Keyword("name".into()), Token::Symbol("name".into())]),
+            lex_tokens(":name name")
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let (tokens, _) = lex(StringStream::new("(foo\n  \"bar\")")).unwrap();
+        let spans = tokens.into_iter().map(|(_, span)| span).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Span::new(None, Position::new(1, 1), Position::new(1, 2)),
+                Span::new(None, Position::new(1, 2), Position::new(1, 5)),
+                Span::new(None, Position::new(2, 3), Position::new(2, 8)),
+                Span::new(None, Position::new(2, 8), Position::new(2, 9)),
+            ],
+            spans
         );
     }
 
@@ -214,6 +709,45 @@ mod test {
             Ok((Token::from(x), "")) == parser(literal).parse(&*x.to_string())
         }
 
+        fn signed_int_literal(x: u32) -> bool {
+            let x = i64::from(x);
+            Ok(vec![Token::from(x), Token::from(-x)]) == lex_tokens(&format!("+{} -{}", x, x))
+        }
+
+        fn radix_int_literal(x: i64) -> bool {
+            let sign = if x < 0 { "-" } else { "" };
+            let y = x.unsigned_abs();
+            let inputs = [
+                format!("{}0x{:x}", sign, y),
+                format!("{}0X{:X}", sign, y),
+                format!("{}0o{:o}", sign, y),
+                format!("{}0b{:b}", sign, y),
+            ];
+            inputs.iter().all(|input| Ok((Token::from(x), "")) == parser(literal).parse(&**input))
+        }
+
+        fn separated_int_literal(x: i64) -> bool {
+            // Group the digits in threes, e.g. 1_234_567
+            let digits = x.unsigned_abs().to_string();
+            let first = digits.len() % 3;
+            let mut groups = vec![&digits[..first]];
+            groups.extend((first..digits.len()).step_by(3).map(|i| &digits[i..i + 3]));
+            groups.retain(|group| !group.is_empty());
+            let input = format!("{}{}", if x < 0 { "-" } else { "" }, groups.join("_"));
+            Ok((Token::from(x), "")) == parser(literal).parse(&*input)
+        }
+
+        fn exponent_literal(x: f64) -> bool {
+            if !x.is_finite() {
+                return true;
+            }
+            let inputs = [format!("{:e}", x), format!("{:E}", x), format!("{:+e}", x)];
+            inputs.iter().all(|input| match parser(literal).parse(&**input) {
+                Ok((Token::Literal(Literal::Flt(y)), "")) => x.approx_eq_ulps(&y, 4),
+                _ => false,
+            })
+        }
+
         fn float_literal(x: f64) -> bool {
             let mut string = x.to_string();
             if x.trunc() == x {
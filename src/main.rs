@@ -22,6 +22,7 @@ extern crate conv;
 extern crate itertools;
 extern crate unicode_xid;
 extern crate rustyline;
+extern crate term_size;
 
 mod buffer;
 mod types;
@@ -36,20 +37,16 @@ mod util;
 mod input;
 mod env;
 mod stream;
+mod span;
+mod diagnostic;
+mod cst;
+mod reader;
+mod pprint;
+mod formatter;
 
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 
 fn main() {
-    use std::sync::Arc;
-    println!("Expr: {}", ::std::mem::size_of::<types::Expr>());
-    println!("String: {}", ::std::mem::size_of::<String>());
-    println!("Symbol: {}", ::std::mem::size_of::<types::Symbol>());
-    println!("Arc<Fn>: {}", ::std::mem::size_of::<Arc<types::Function>>());
-    println!("Arc<Macro>: {}", ::std::mem::size_of::<Arc<types::Macro>>());
-    println!("List: {}", ::std::mem::size_of::<types::List>());
-    println!("Vector: {}", ::std::mem::size_of::<types::Vector>());
-    println!("Map: {}", ::std::mem::size_of::<types::Map>());
-
     let matches = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
@@ -60,18 +57,35 @@ fn main() {
         .arg(Arg::from_usage(
             "[input] 'Read program from file (- for stdin)'",
         ))
+        .subcommand(SubCommand::with_name("fmt")
+            .about("Formats source files in place")
+            .arg(Arg::from_usage("--check 'Only report files which are not formatted'"))
+            .arg(Arg::from_usage("<files>... 'Files to format'")))
         .get_matches();
 
+    if let Some(fmt) = matches.subcommand_matches("fmt") {
+        let files: Vec<_> = fmt.values_of("files").unwrap().collect();
+        let check = fmt.is_present("check");
+        match formatter::format_files(&files, check) {
+            Ok(formatted) if formatted || !check => return,
+            Ok(_) => std::process::exit(1),
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let env = ops::env();
 
     if let Some(file) = matches.value_of("input") {
-        if file == "-" {
-            
+        let result = if file == "-" {
+            input::stdin(env.clone())
         } else {
-            match input::file(file, env.clone()) {
-                Ok(_) => (),
-                Err(err) => println!("{}", err),
-            }
+            input::file(file, env.clone())
+        };
+        if let Err(err) = result {
+            println!("{}", err);
         }
     }
 
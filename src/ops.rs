@@ -1,9 +1,14 @@
 use std::collections::HashMap;
 use std::ops::{Sub, Div};
+use std::rc::Rc;
+use conv::ValueFrom;
 use itertools::Itertools;
 use error::*;
 use env::Env;
-use types::{Expr, List, Vector, Function, Lambda};
+use input;
+use pprint::Printer;
+use reader::Reader;
+use types::{Expr, List, Vector, Map, Set, Key, Keyword, Function, Lambda};
 use util::*;
 
 pub fn env() -> Env {
@@ -22,9 +27,35 @@ pub fn env() -> Env {
         ("rest", rest),
         ("cons", cons),
         ("list", list),
+        ("char->int", char_to_int),
+        ("int->char", int_to_char),
+        ("string->list", string_to_list),
+        ("hash-map", hash_map),
+        ("get", get),
+        ("assoc", assoc),
+        ("dissoc", dissoc),
+        ("keys", keys),
+        ("vals", vals),
+        ("hash-set", hash_set),
+        ("set", set),
+        ("conj", conj),
+        ("disj", disj),
+        ("contains?", contains),
+        ("union", union),
+        ("intersection", intersection),
+        ("difference", difference),
+        ("str", str),
+        ("pr-str", pr_str),
         ("print", print),
+        ("pprint", pprint),
+        ("pprint-str", pprint_str),
         ("debug", debug),
+        ("meta", meta),
+        ("with-meta", with_meta),
         ("eval", eval),
+        ("read-string", read_string),
+        ("read-all-string", read_all_string),
+        ("read", read),
         ("exit", exit),
     ];
 
@@ -52,8 +83,8 @@ where
             // If any are float, promote to float
             let floats = args.iter()
                 .map(|x| match *x {
-                    Expr::Int(y) => y as f64,
-                    Expr::Flt(y) => y,
+                    Expr::Int(y, _) => y as f64,
+                    Expr::Flt(y, _) => y,
                     _ => unreachable!(),
                 })
                 .collect::<Vec<_>>();
@@ -62,7 +93,7 @@ where
             // Otherwise perform integer operation
             let ints = args.iter()
                 .map(|x| match *x {
-                    Expr::Int(y) => y,
+                    Expr::Int(y, _) => y,
                     _ => unreachable!(),
                 })
                 .collect::<Vec<_>>();
@@ -86,8 +117,8 @@ fn sub(args: &[Expr], _env: Env) -> Result<Expr> {
     // If one argument, negate and return
     if args.len() == 1 {
         return match args[0] {
-            Expr::Int(x) => Ok(Expr::from(-x)),
-            Expr::Flt(x) => Ok(Expr::from(-x)),
+            Expr::Int(x, _) => Ok(Expr::from(-x)),
+            Expr::Flt(x, _) => Ok(Expr::from(-x)),
             _ => Err("invalid type".into())
         }
     }
@@ -111,8 +142,8 @@ fn div(args: &[Expr], _env: Env) -> Result<Expr> {
     // If one argument, invert and return
     if args.len() == 1 {
         return match args[0] {
-            Expr::Int(x) => Ok(Expr::from((x as f64).recip())),
-            Expr::Flt(x) => Ok(Expr::from(x.recip())),
+            Expr::Int(x, _) => Ok(Expr::from((x as f64).recip())),
+            Expr::Flt(x, _) => Ok(Expr::from(x.recip())),
             _ => Err("invalid type".into())
         }
     }
@@ -137,9 +168,10 @@ fn equal(args: &[Expr], _env: Env) -> Result<Expr> {
 fn less(args: &[Expr], _env: Env) -> Result<Expr> {
     ensure_args("<", args, 2)?;
     match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a < b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a < b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a < b)),
+        (&Expr::Int(ref a, _), &Expr::Int(ref b, _)) => Ok(Expr::from(a < b)),
+        (&Expr::Flt(ref a, _), &Expr::Flt(ref b, _)) => Ok(Expr::from(a < b)),
+        (&Expr::Str(ref a, _), &Expr::Str(ref b, _)) => Ok(Expr::from(a < b)),
+        (&Expr::Char(ref a, _), &Expr::Char(ref b, _)) => Ok(Expr::from(a < b)),
         _ => Err(
             format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
         ),
@@ -149,9 +181,10 @@ fn less(args: &[Expr], _env: Env) -> Result<Expr> {
 fn less_eq(args: &[Expr], _env: Env) -> Result<Expr> {
     ensure_args("<=", args, 2)?;
     match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a <= b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a <= b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a <= b)),
+        (&Expr::Int(ref a, _), &Expr::Int(ref b, _)) => Ok(Expr::from(a <= b)),
+        (&Expr::Flt(ref a, _), &Expr::Flt(ref b, _)) => Ok(Expr::from(a <= b)),
+        (&Expr::Str(ref a, _), &Expr::Str(ref b, _)) => Ok(Expr::from(a <= b)),
+        (&Expr::Char(ref a, _), &Expr::Char(ref b, _)) => Ok(Expr::from(a <= b)),
         _ => Err(
             format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
         ),
@@ -161,9 +194,10 @@ fn less_eq(args: &[Expr], _env: Env) -> Result<Expr> {
 fn greater(args: &[Expr], _env: Env) -> Result<Expr> {
     ensure_args(">", args, 2)?;
     match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a > b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a > b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a > b)),
+        (&Expr::Int(ref a, _), &Expr::Int(ref b, _)) => Ok(Expr::from(a > b)),
+        (&Expr::Flt(ref a, _), &Expr::Flt(ref b, _)) => Ok(Expr::from(a > b)),
+        (&Expr::Str(ref a, _), &Expr::Str(ref b, _)) => Ok(Expr::from(a > b)),
+        (&Expr::Char(ref a, _), &Expr::Char(ref b, _)) => Ok(Expr::from(a > b)),
         _ => Err(
             format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
         ),
@@ -173,9 +207,10 @@ fn greater(args: &[Expr], _env: Env) -> Result<Expr> {
 fn greater_eq(args: &[Expr], _env: Env) -> Result<Expr> {
     ensure_args(">=", args, 2)?;
     match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a >= b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a >= b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a >= b)),
+        (&Expr::Int(ref a, _), &Expr::Int(ref b, _)) => Ok(Expr::from(a >= b)),
+        (&Expr::Flt(ref a, _), &Expr::Flt(ref b, _)) => Ok(Expr::from(a >= b)),
+        (&Expr::Str(ref a, _), &Expr::Str(ref b, _)) => Ok(Expr::from(a >= b)),
+        (&Expr::Char(ref a, _), &Expr::Char(ref b, _)) => Ok(Expr::from(a >= b)),
         _ => Err(
             format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
         ),
@@ -188,15 +223,73 @@ fn not(args: &[Expr], _env: Env) -> Result<Expr> {
     Ok(Expr::from(!args[0].truthiness()))
 }
 
-// (print expr)
-// TODO: lift one-argument restriction
+// (str exprs*)
+// The values joined together, with strings and chars as their raw text
+fn str(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(args.iter().map(Expr::to_raw_string).join("")))
+}
+
+// (pr-str exprs*)
+// The values separated by spaces, printed so that they read back as equal values
+fn pr_str(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(args.iter().join(" ")))
+}
+
+// (print exprs*)
 // TODO: create print, println versions
 fn print(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("print", args, 1)?;
-    println!("{}", args[0]);
+    println!("{}", args.iter().map(Expr::to_raw_string).join(" "));
+    Ok(Expr::Nil)
+}
+
+// (pprint expr options?)
+// The options map may have a :width, an :indent, and :bodies, which maps forms
+// to how many of their arguments come before a body indented like code
+fn pprint(args: &[Expr], _env: Env) -> Result<Expr> {
+    println!("{}", pretty("pprint", args)?);
     Ok(Expr::Nil)
 }
 
+// (pprint-str expr options?)
+// What pprint would print
+fn pprint_str(args: &[Expr], _env: Env) -> Result<Expr> {
+    pretty("pprint-str", args).map(Expr::from)
+}
+
+fn pretty(name: &str, args: &[Expr]) -> Result<String> {
+    ensure_range_args(name, args, 1, 2)?;
+    let printer = match args.get(1) {
+        Some(options) => printer(ensure_map(name, options)?)?,
+        None => Printer::default(),
+    };
+    Ok(printer.print(&args[0]))
+}
+
+fn printer(options: &Map) -> Result<Printer> {
+    let option = |name| options.get(&Key::Keyword(Keyword::new(name)));
+    let size = |name, value| {
+        let size = ensure_int("pprint", value)?;
+        usize::value_from(size).map_err(|_| Error::from(format!("#[pprint] invalid {}: {}", name, size)))
+    };
+
+    let mut printer = match option("width") {
+        Some(width) => Printer::new(size("width", width)?),
+        None => Printer::default(),
+    };
+    if let Some(indent) = option("indent") {
+        printer = printer.with_indent(size("indent", indent)?);
+    }
+    if let Some(bodies) = option("bodies") {
+        for (form, args) in ensure_map("pprint", bodies)?.iter() {
+            match *form {
+                Key::Sym(ref form) => printer = printer.with_body(&form.name, size("body", args)?),
+                _ => bail!("#[pprint] expected symbol, found {}", form),
+            }
+        }
+    }
+    Ok(printer)
+}
+
 // (debug expr)
 // TODO: lift one-argument restriction
 fn debug(args: &[Expr], _env: Env) -> Result<Expr> {
@@ -209,8 +302,8 @@ fn debug(args: &[Expr], _env: Env) -> Result<Expr> {
 fn first(args: &[Expr], _env: Env) -> Result<Expr> {
     ensure_args("first", args, 1)?;
     match args[0] {
-        Expr::List(ref l) => Ok(l.0.first().cloned().unwrap_or(Expr::Nil)),
-        Expr::Vector(ref q) => Ok(q.0.first().cloned().unwrap_or(Expr::Nil)),
+        Expr::List(ref l) => Ok(l.items.first().cloned().unwrap_or(Expr::Nil)),
+        Expr::Vector(ref q) => Ok(q.items.first().cloned().unwrap_or(Expr::Nil)),
         _ => Err("#[first] expected list".into()),
     }
 }
@@ -221,17 +314,17 @@ fn rest(args: &[Expr], _env: Env) -> Result<Expr> {
     match args[0] {
         Expr::List(ref l) => {
             Ok(
-                l.0
+                l.items
                     .split_first()
-                    .map(|(_, rest)| Expr::List(List(rest.to_vec())))
+                    .map(|(_, rest)| Expr::List(List::new(rest.to_vec())))
                     .unwrap_or(Expr::Nil),
             )
         }
         Expr::Vector(ref v) => {
             Ok(
-                v.0
+                v.items
                     .split_first()
-                    .map(|(_, rest)| Expr::Vector(Vector(rest.to_vec())))
+                    .map(|(_, rest)| Expr::Vector(Vector::new(rest.to_vec())))
                     .unwrap_or(Expr::Nil),
             )
         }
@@ -246,12 +339,12 @@ fn cons(args: &[Expr], _env: Env) -> Result<Expr> {
     match args[2] {
         Expr::List(ref l) => {
             let mut new = l.clone();
-            new.0.insert(0, args[1].clone());
+            new.items.insert(0, args[1].clone());
             Ok(Expr::List(new))
         }
         Expr::Vector(ref v) => {
             let mut new = v.clone();
-            new.0.push(args[1].clone());
+            new.items.push(args[1].clone());
             Ok(Expr::Vector(new))
         }
         _ => Err("#[cons] expected list".into()),
@@ -263,17 +356,342 @@ fn list(args: &[Expr], _env: Env) -> Result<Expr> {
     if args.is_empty() {
         Ok(Expr::Nil)
     } else {
-        Ok(Expr::List(List(args.to_vec())))
+        Ok(Expr::List(List::new(args.to_vec())))
+    }
+}
+
+// (char->int char)
+fn char_to_int(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_args("char->int", args, 1)?;
+    let c = ensure_char("char->int", &args[0])?;
+    Ok(Expr::from(c as i64))
+}
+
+// (int->char int)
+fn int_to_char(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_args("int->char", args, 1)?;
+    let i = ensure_int("int->char", &args[0])?;
+    u32::value_from(i).ok()
+        .and_then(char::from_u32)
+        .map(Expr::from)
+        .ok_or_else(|| format!("#[int->char] invalid character code: {}", i).into())
+}
+
+// (string->list string)
+fn string_to_list(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("string->list", args, 1)?;
+    let string = ensure_str("string->list", &args[0])?;
+    list(&string.chars().map(Expr::from).collect::<Vec<_>>(), env)
+}
+
+// (hash-map (key value)*)
+fn hash_map(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure!(args.len().is_multiple_of(2), "#[hash-map] {}", odd_forms("args"));
+    assoc_pairs("hash-map", Map::new(), args).map(Expr::Map)
+}
+
+fn assoc_pairs(fn_name: &str, mut map: Map, pairs: &[Expr]) -> Result<Map> {
+    for pair in pairs.chunks(2) {
+        let key = Key::try_from(&pair[0]).map_err(|err| format!("#[{}] {}", fn_name, err))?;
+        map.insert(key, pair[1].clone());
+    }
+    Ok(map)
+}
+
+// (get map key default?)
+fn get(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_range_args("get", args, 2, 3)?;
+    let default = args.get(2).cloned().unwrap_or(Expr::Nil);
+    match args[0] {
+        Expr::Map(ref m) => {
+            let value = Key::try_from(&args[1]).ok().and_then(|key| m.get(&key).cloned());
+            Ok(value.unwrap_or(default))
+        }
+        Expr::Nil => Ok(default),
+        _ => Err("#[get] expected map".into()),
+    }
+}
+
+// (assoc map (key value)+)
+fn assoc(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_min_args("assoc", args, 3)?;
+    ensure!(args[1..].len().is_multiple_of(2), "#[assoc] {}", odd_forms("keys and values"));
+    let map = match args[0] {
+        Expr::Map(ref m) => m.clone(),
+        Expr::Nil => Map::new(),
+        _ => return Err("#[assoc] expected map".into()),
+    };
+    assoc_pairs("assoc", map, &args[1..]).map(Expr::Map)
+}
+
+// (dissoc map keys*)
+fn dissoc(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_min_args("dissoc", args, 1)?;
+    let mut map = ensure_map("dissoc", &args[0])?.clone();
+    for key in &args[1..] {
+        if let Ok(key) = Key::try_from(key) {
+            map.remove(&key);
+        }
+    }
+    Ok(Expr::Map(map))
+}
+
+// (keys map)
+fn keys(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("keys", args, 1)?;
+    let map = ensure_map("keys", &args[0])?;
+    list(&map.iter().map(|(key, _)| key.to_expr()).collect::<Vec<_>>(), env)
+}
+
+// (vals map)
+fn vals(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("vals", args, 1)?;
+    let map = ensure_map("vals", &args[0])?;
+    list(&map.iter().map(|(_, value)| value.clone()).collect::<Vec<_>>(), env)
+}
+
+// (hash-set items*)
+fn hash_set(args: &[Expr], _env: Env) -> Result<Expr> {
+    set_keys("hash-set", args).map(Expr::Set)
+}
+
+fn set_keys(fn_name: &str, items: &[Expr]) -> Result<Set> {
+    items.iter()
+        .map(|item| Key::try_from(item).map_err(|err| format!("#[{}] {}", fn_name, err).into()))
+        .collect()
+}
+
+// (set seq)
+fn set(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_args("set", args, 1)?;
+    match args[0] {
+        Expr::List(ref l) => set_keys("set", &l.items).map(Expr::Set),
+        Expr::Vector(ref v) => set_keys("set", &v.items).map(Expr::Set),
+        Expr::Set(ref s) => Ok(Expr::Set(s.clone())),
+        Expr::Nil => Ok(Expr::Set(Set::new())),
+        _ => Err("#[set] expected list, vector or set".into()),
     }
 }
 
+// (conj coll items*)
+fn conj(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_min_args("conj", args, 1)?;
+    let items = &args[1..];
+    match args[0] {
+        Expr::Set(ref s) => {
+            let mut new = s.clone();
+            for key in set_keys("conj", items)?.iter() {
+                new.insert(key.clone());
+            }
+            Ok(Expr::Set(new))
+        }
+        Expr::Vector(ref v) => {
+            let mut new = v.clone();
+            new.items.extend(items.iter().cloned());
+            Ok(Expr::Vector(new))
+        }
+        Expr::List(ref l) => {
+            let mut new = l.clone();
+            for item in items {
+                new.items.insert(0, item.clone());
+            }
+            Ok(Expr::List(new))
+        }
+        Expr::Nil => Ok(Expr::List(List::new(items.iter().rev().cloned().collect()))),
+        _ => Err("#[conj] expected list, vector or set".into()),
+    }
+}
+
+// (disj set items*)
+fn disj(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_min_args("disj", args, 1)?;
+    let mut set = ensure_set("disj", &args[0])?.clone();
+    for item in &args[1..] {
+        if let Ok(key) = Key::try_from(item) {
+            set.remove(&key);
+        }
+    }
+    Ok(Expr::Set(set))
+}
+
+// (contains? coll key)
+fn contains(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_args("contains?", args, 2)?;
+    let key = Key::try_from(&args[1]).ok();
+    match args[0] {
+        Expr::Set(ref s) => Ok(Expr::from(key.is_some_and(|key| s.contains(&key)))),
+        Expr::Map(ref m) => Ok(Expr::from(key.is_some_and(|key| m.get(&key).is_some()))),
+        Expr::Nil => Ok(Expr::from(false)),
+        _ => Err("#[contains?] expected set or map".into()),
+    }
+}
+
+// Fold sets together with a set operation, e.g. (union #{1} #{2} #{3})
+fn set_op<F>(fn_name: &str, args: &[Expr], op: F) -> Result<Expr>
+where
+    F: Fn(&Set, &Set) -> Set,
+{
+    ensure_min_args(fn_name, args, 1)?;
+    let mut result = ensure_set(fn_name, &args[0])?.clone();
+    for arg in &args[1..] {
+        result = op(&result, ensure_set(fn_name, arg)?);
+    }
+    Ok(Expr::Set(result))
+}
+
+// (union sets+)
+fn union(args: &[Expr], _env: Env) -> Result<Expr> {
+    set_op("union", args, Set::union)
+}
+
+// (intersection sets+)
+fn intersection(args: &[Expr], _env: Env) -> Result<Expr> {
+    set_op("intersection", args, Set::intersection)
+}
+
+// (difference sets+)
+fn difference(args: &[Expr], _env: Env) -> Result<Expr> {
+    set_op("difference", args, Set::difference)
+}
+
+// (meta expr)
+fn meta(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_args("meta", args, 1)?;
+    Ok(args[0].meta().cloned().map(Expr::Map).unwrap_or(Expr::Nil))
+}
+
+// (with-meta expr map)
+fn with_meta(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_args("with-meta", args, 2)?;
+    let meta = match args[1] {
+        Expr::Map(ref m) => Some(Rc::new(m.clone())),
+        Expr::Nil => None,
+        _ => return Err("#[with-meta] expected map".into()),
+    };
+    args[0].with_meta(meta).map_err(|err| format!("#[with-meta] {}", err).into())
+}
+
 // (eval form)
 fn eval(args: &[Expr], env: Env) -> Result<Expr> {
     ensure_args("eval", args, 1)?;
     args[0].eval(env)
 }
 
+// (read-string string)
+// The first form in the string, or nil if there isn't one
+fn read_string(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure_args("read-string", args, 1)?;
+    let string = ensure_str("read-string", &args[0])?;
+    let form = Reader::new(string.as_bytes(), Some("<string>")).read()?;
+    Ok(form.unwrap_or(Expr::Nil))
+}
+
+// (read-all-string string)
+fn read_all_string(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("read-all-string", args, 1)?;
+    let string = ensure_str("read-all-string", &args[0])?;
+    list(&input::read_all(string.as_bytes(), Some("<string>"))?, env)
+}
+
+// (read)
+// The next form from standard input, or nil at the end of it. There are no
+// input ports, so standard input is the only thing it reads from.
+fn read(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure!(args.is_empty(), "#[read] only reads from standard input, so takes no port or other args");
+    let form = input::read_stdin()?;
+    Ok(form.unwrap_or(Expr::Nil))
+}
+
 // (exit)
 fn exit(_args: &[Expr], _env: Env) -> Result<Expr> {
     Err(ErrorKind::Exit(0).into())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use util::testing::*;
+
+    #[test]
+    fn map_builtins() {
+        let printed = eval_printed("(get (assoc (dissoc {:a 1 :b 2} :a) :c 3) :c) (keys {:a 1})", &env());
+        assert_eq!(vec!["3", "(:a)"], printed);
+    }
+
+    #[test]
+    fn chars() {
+        let values = eval_all("(char->int \\a) (int->char 233) (string->list \"ab\") (< \\a \\b) \\space", &env());
+        assert_eq!(
+            vec![
+                Expr::from(97),
+                Expr::from('é'),
+                Expr::List(List::new(vec![Expr::from('a'), Expr::from('b')])),
+                Expr::from(true),
+                Expr::from(' '),
+            ],
+            values
+        );
+        assert_eq!("\\space \\a", format!("{} {}", values[4], Expr::from('a')));
+    }
+
+    #[test]
+    fn meta() {
+        let printed = eval_printed("(def ^{:doc \"the answer\"} x ^:private [42])
+            (meta x)
+            (= x [42])
+            (def f (with-meta (fn [] 1) {:test #t}))
+            (meta f)
+            (meta (with-meta f ()))
+            (meta 'y)", &env());
+        assert_eq!("{:doc \"the answer\" :private #t}", printed[1]);
+        assert_eq!("#t", printed[2]);
+        assert_eq!(vec!["{:test #t}", "nil", "nil"], printed[4..].to_vec());
+    }
+
+    #[test]
+    fn read_builtins() {
+        let env = env();
+        let values = eval_all("(read-string \"(+ 1\n 2) ignored\")
+            (eval (read-string \"(+ 1 2)\"))
+            (read-string \"#_ 1\")
+            (read-all-string \"1 [2] #{:c}\")
+            (read-all-string \"\")", &env);
+        assert_eq!("(+ 1 2)", values[0].to_string());
+        assert_eq!(2, values[0].span().unwrap().end.line);
+        assert_eq!(Expr::from(3), values[1]);
+        assert_eq!(Expr::Nil, values[2]);
+        assert_eq!("(1 [2] #{:c})", values[3].to_string());
+        assert_eq!(Expr::Nil, values[4]);
+
+        let errors = eval_errors("(read-string \"(+ 1\")
+            (read-string \"[1 2)\")
+            (read-all-string \"1x 2 (3\")
+            (read :port)", &env);
+        assert_eq!(
+            vec![
+                "<string>:1:1: incomplete form, expected more input",
+                "<string>:1:1: unclosed `[`: expected `]`, found `)` at 1:5",
+                "<string>:1:1: unexpected `1x`\n<string>:1:6: unclosed `(`: expected `)`, found end of input",
+                "test.tele:4:13: #[read] only reads from standard input, so takes no port or other args",
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn pprint_options() {
+        assert_eq!(
+            vec!["\"(when x\\n    y)\""],
+            eval_printed("(pprint-str '(when x y) {:width 8 :indent 4 :bodies {'when 1}})", &env())
+        );
+        let errors = eval_errors("(pprint 1 {:width -1})
+            (pprint 1 {:bodies {:when 1}})", &env());
+        assert_eq!(
+            vec![
+                "test.tele:1:1: #[pprint] invalid width: -1",
+                "test.tele:2:13: #[pprint] expected symbol, found :when",
+            ],
+            errors
+        );
+    }
+}
@@ -1,109 +1,708 @@
+use std::cmp;
+
 use combine::{Stream, Parser, ParseError, ParseResult};
-use combine::{between, many, parser, satisfy_map, token, try, not_followed_by};
+use combine::{many, parser, position, satisfy_map, skip_many, token, not_followed_by};
+use combine::primitives::{Consumed, Error, Info};
+use diagnostic::{describe, Diagnostic};
+use span::Span;
+use stream::{self, TokenStream};
 use token::Token;
-use types::{Expr, List, Vector, Symbol};
+use types::{Expr, Key, Keyword, List, Map, Set, Vector, Symbol};
+use util::odd_forms;
 
 pub fn parse<I>(input: I) -> Result<(Vec<Expr>, I), ParseError<I>>
 where
-    I: Stream<Item = Token>,
+    I: Stream<Item = Token, Position = Span>,
 {
     // Balanced delimiters
-    many(parser(expr))
+    parser(|input| exprs(input, false))
         .skip(not_followed_by(token(Token::RParen)))
         .skip(not_followed_by(token(Token::RBracket)))
+        .skip(not_followed_by(token(Token::RBrace)))
+        .parse(input)
+}
+
+// A single top-level form, or None if it was discarded with #_
+pub fn parse_form<I>(input: I) -> Result<(Option<Expr>, I), ParseError<I>>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    parser(|input| discard(input, false)).map(|_| None)
+        .or(parser(|input| expr(input, false)).map(Some))
         .parse(input)
 }
 
-fn expr<I>(input: I) -> ParseResult<Expr, I>
+// Parse every top-level form, skipping to the next one after an error so that
+// every error in the source is reported rather than just the first
+pub fn parse_all(tokens: &[(Token, Span)]) -> (Vec<Expr>, Vec<Diagnostic>) {
+    let nesting = Nesting::new(tokens);
+    let stream = TokenStream::shared(tokens.into());
+    let mut exprs = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut index = 0;
+
+    while index < tokens.len() {
+        match parse_form(stream.seek(index)) {
+            Ok((expr, rest)) => {
+                exprs.extend(expr);
+                index = rest.offset();
+            }
+            Err(err) => {
+                // Where the error is, or past the last token if the input ended early
+                let at = if stream::is_incomplete(&err) {
+                    tokens.len()
+                } else {
+                    tokens[index..].iter()
+                        .position(|(_, span)| *span == err.position)
+                        .map_or(index, |offset| index + offset)
+                };
+                diagnostics.push(nesting.diagnose(tokens, at, &err));
+                index = nesting.next_top_level(at);
+            }
+        }
+    }
+
+    (exprs, diagnostics)
+}
+
+// How deeply each token is nested, and in which opening delimiter
+struct Nesting {
+    depth: Vec<usize>,
+    opener: Vec<Option<usize>>,
+}
+
+impl Nesting {
+    fn new(tokens: &[(Token, Span)]) -> Self {
+        let mut open = Vec::new();
+        let mut depth = Vec::with_capacity(tokens.len() + 1);
+        let mut opener = Vec::with_capacity(tokens.len() + 1);
+
+        for (index, (token, _)) in tokens.iter().enumerate() {
+            depth.push(open.len());
+            opener.push(open.last().cloned());
+            if token.closer().is_some() {
+                open.push(index);
+            } else if token.is_closer() {
+                // A mismatched closer still closes, so that it is reported only once
+                open.pop();
+            }
+        }
+
+        // Past the end of the tokens
+        depth.push(open.len());
+        opener.push(open.last().cloned());
+        Nesting { depth, opener }
+    }
+
+    // The first token after `at` which starts a top-level form
+    fn next_top_level(&self, at: usize) -> usize {
+        (at + 1..self.depth.len())
+            .find(|&index| self.depth[index] == 0)
+            .unwrap_or(self.depth.len() - 1)
+    }
+
+    // A closing delimiter in the wrong place is reported at the form it fails to close
+    fn diagnose(&self, tokens: &[(Token, Span)], at: usize, err: &ParseError<TokenStream>) -> Diagnostic {
+        let found = tokens.get(at);
+        match (found, self.opener[at]) {
+            (Some((token, span)), None) if token.is_closer() => {
+                Diagnostic::new(span.clone(), format!("unmatched `{}`", token.source()))
+            }
+            (Some((token, span)), Some(opener)) if token.is_closer() => {
+                let (ref open, ref open_span) = tokens[opener];
+                let message = format!(
+                    "unclosed `{}`: expected `{}`, found `{}` at {}",
+                    open.source(), open.closer().unwrap().source(), token.source(), span.start
+                );
+                Diagnostic::new(open_span.clone(), message)
+            }
+            (None, Some(opener)) => {
+                let (ref open, ref open_span) = tokens[opener];
+                let message = format!(
+                    "unclosed `{}`: expected `{}`, found end of input",
+                    open.source(), open.closer().unwrap().source()
+                );
+                Diagnostic::new(open_span.clone(), message)
+            }
+            _ => Diagnostic::new(err.position.clone(), describe(&source_errors(&err.errors))),
+        }
+    }
+}
+
+// Show tokens in errors as they are written in source, e.g. `)` rather than `RParen`
+fn source_errors(errors: &[Error<Token, Token>]) -> Vec<Error<String, String>> {
+    let source = |info: &Info<Token, Token>| match *info {
+        Info::Token(ref token) | Info::Range(ref token) => Info::Token(token.source()),
+        Info::Owned(ref s) => Info::Owned(s.clone()),
+        Info::Borrowed(s) => Info::Borrowed(s),
+    };
+
+    errors.iter()
+        .map(|error| match *error {
+            Error::Unexpected(ref info) => Error::Unexpected(source(info)),
+            Error::Expected(ref info) => Error::Expected(source(info)),
+            Error::Message(ref info) => Error::Message(source(info)),
+            Error::Other(ref err) => Error::Message(Info::Owned(err.to_string())),
+        })
+        .collect()
+}
+
+// A sequence of forms, dropping any discarded with #_. Every form parser takes
+// `in_anon_fn`, whether it's reading inside #(...), and passes it on to the forms in it.
+fn exprs<I>(input: I, in_anon_fn: bool) -> ParseResult<Vec<Expr>, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    let item = parser(move |input| discard(input, in_anon_fn)).map(|_| None)
+        .or(parser(move |input| expr(input, in_anon_fn)).map(Some));
+
+    many::<Vec<_>, _>(item)
+        .map(|items| items.into_iter().flatten().collect())
+        .parse_stream(input)
+}
+
+// A single form, skipping any preceding #_ forms
+fn form<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    skip_many(parser(move |input| discard(input, in_anon_fn)))
+        .with(parser(move |input| expr(input, in_anon_fn)))
+        .parse_stream(input)
+}
+
+// #_ form
+fn discard<I>(input: I, in_anon_fn: bool) -> ParseResult<(), I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    token(Token::Discard)
+        .with(parser(move |input| form(input, in_anon_fn)))
+        .map(|_| ())
+        .parse_stream(input)
+}
+
+fn expr<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
 where
-    I: Stream<Item = Token>,
+    I: Stream<Item = Token, Position = Span>,
 {
     choice!(
         parser(atom),
-        parser(quote),
-        parser(list),
-        parser(vector)
+        parser(move |input| quote(input, in_anon_fn)),
+        parser(move |input| quasiquote(input, in_anon_fn)),
+        parser(move |input| unquote(input, in_anon_fn)),
+        parser(move |input| unquote_splicing(input, in_anon_fn)),
+        parser(move |input| meta(input, in_anon_fn)),
+        parser(move |input| list(input, in_anon_fn)),
+        parser(move |input| anon_fn(input, in_anon_fn)),
+        parser(move |input| vector(input, in_anon_fn)),
+        parser(move |input| map(input, in_anon_fn)),
+        parser(move |input| set(input, in_anon_fn))
     ).parse_stream(input)
 }
 
-fn quote<I>(input: I) -> ParseResult<Expr, I>
+fn quote<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    reader_macro(Token::Quote, "quote", in_anon_fn).parse_stream(input)
+}
+
+fn quasiquote<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
 where
-    I: Stream<Item = Token>,
+    I: Stream<Item = Token, Position = Span>,
+{
+    reader_macro(Token::Quasiquote, "quasiquote", in_anon_fn).parse_stream(input)
+}
+
+fn unquote<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    reader_macro(Token::Unquote, "unquote", in_anon_fn).parse_stream(input)
+}
+
+fn unquote_splicing<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    reader_macro(Token::UnquoteSplicing, "unquote-splicing", in_anon_fn).parse_stream(input)
+}
+
+// A prefix token followed by a form, e.g. 'x => (quote x)
+fn reader_macro<I>(prefix: Token, name: &'static str, in_anon_fn: bool) -> impl Parser<Input = I, Output = Expr>
+where
+    I: Stream<Item = Token, Position = Span>,
 {
     (
-        token(Token::Quote),
-        parser(expr)
+        position(),
+        token(prefix),
+        parser(move |input| form(input, in_anon_fn))
     )
-    .map(|(_, expr)| {
-        let quote_symbol = Expr::Sym(Symbol("quote".into()));
-        Expr::List(List(vec![quote_symbol, expr]))
-    }).parse_stream(input)
+    .map(move |(start, _, expr): (Span, _, Expr)| {
+        let span = expr.span().map(|end| start.to(end)).unwrap_or_else(|| start.clone());
+        let symbol = Expr::Sym(Symbol::new(name).with_span(start));
+        Expr::List(List::new(vec![symbol, expr]).with_span(span))
+    })
+}
+
+// ^{:k v} form, ^:flag form or ^Tag form attaches metadata to the form
+fn meta<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    (
+        token(Token::Meta),
+        parser(move |input| form(input, in_anon_fn)),
+        parser(move |input| form(input, in_anon_fn)),
+    )
+    .and_then(|(_, meta, target)| {
+        meta_map(meta)
+            .and_then(|meta| target.merge_meta(&meta).map_err(|err| err.to_string()))
+            .map_err(|msg| Error::Message(Info::Owned(msg)))
+    })
+    .parse_stream(input)
+}
+
+fn meta_map(meta: Expr) -> Result<Map, String> {
+    let mut map = Map::new();
+    match meta {
+        Expr::Map(map) => return Ok(map),
+        Expr::Keyword(keyword) => map.insert(Key::Keyword(keyword), Expr::from(true)),
+        Expr::Sym(_) | Expr::Str(..) => map.insert(Key::Keyword(Keyword::new("tag")), meta),
+        _ => return Err(format!("metadata must be a map, keyword, symbol or string: {}", meta)),
+    };
+    Ok(map)
 }
 
 fn atom<I>(input: I) -> ParseResult<Expr, I>
 where
-    I: Stream<Item = Token>,
+    I: Stream<Item = Token, Position = Span>,
 {
-    satisfy_map(|token| match token {
-        Token::Literal(lit) => Some(Expr::from(lit)),
-        Token::Symbol(sym) => {
-            if sym == "nil" {
-                Some(Expr::Nil)
-            } else {
-                Some(Expr::from(Symbol(sym)))
-            }
-        },
-        _ => None,
-    }).parse_stream(input)
+    (
+        position(),
+        satisfy_map(|token| match token {
+            Token::Literal(lit) => Some(Expr::from(lit)),
+            Token::Keyword(name) => Some(Expr::Keyword(Keyword::new(&name))),
+            Token::Symbol(sym) => {
+                if sym == "nil" {
+                    Some(Expr::Nil)
+                } else {
+                    Some(Expr::from(Symbol::parse(&sym)))
+                }
+            },
+            _ => None,
+        })
+    )
+    .map(|(span, expr): (Span, Expr)| expr.with_span(span))
+    .parse_stream(input)
+}
+
+fn list<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    (
+        position(),
+        token(Token::LParen),
+        parser(move |input| exprs(input, in_anon_fn)),
+        position(),
+        token(Token::RParen),
+    )
+    .map(|(start, _, items, end, _): (Span, _, _, Span, _)| Expr::List(List::new(items).with_span(start.to(&end))))
+    .parse_stream(input)
 }
 
-fn list<I>(input: I) -> ParseResult<Expr, I>
+// #(+ % %2) => (fn [%1 %2] (+ %1 %2)), with %& collecting any further args.
+// The forms in its body are read knowing they're in one, as they can't contain another.
+fn anon_fn<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
 where
-    I: Stream<Item = Token>,
+    I: Stream<Item = Token, Position = Span>,
 {
-        try(between(
-            token(Token::LParen),
+    let (start, rest) = position().skip(token(Token::AnonFn)).parse_stream(input)?;
+    rest.combine(|input| {
+        if in_anon_fn {
+            let err = Error::Message(Info::Borrowed("nested #() is not allowed"));
+            return Err(Consumed::Empty(ParseError::new(start, err)));
+        }
+
+        (
+            parser(|input| exprs(input, true)),
+            position(),
             token(Token::RParen),
-            many(parser(expr)).map(List).map(Expr::List),
-        ))
+        )
+        .and_then(|(items, end, _): (_, Span, _)| {
+            expand_anon_fn(items, start.to(&end)).map_err(|msg| Error::Message(Info::Owned(msg)))
+        })
         .parse_stream(input)
+    })
+}
+
+fn expand_anon_fn(items: Vec<Expr>, span: Span) -> Result<Expr, String> {
+    let mut arity = 0;
+    let mut rest = false;
+    let body = anon_fn_args(Expr::List(List::new(items).with_span(span.clone())), &mut arity, &mut rest)?;
+
+    let mut params = (1..arity + 1)
+        .map(|n| Expr::from(Symbol::new(format!("%{}", n))))
+        .collect::<Vec<_>>();
+    if rest {
+        params.push(Expr::from(Symbol::new("&")));
+        params.push(Expr::from(Symbol::new("%&")));
+    }
+
+    let fn_symbol = Expr::Sym(Symbol::new("fn").with_span(span.clone()));
+    Ok(Expr::List(List::new(vec![fn_symbol, Expr::Vector(Vector::new(params)), body]).with_span(span)))
+}
+
+// Rename % to %1, noting the highest numbered argument and whether %& is used
+fn anon_fn_args(expr: Expr, arity: &mut usize, rest: &mut bool) -> Result<Expr, String> {
+    Ok(match expr {
+        Expr::Sym(mut sym) if sym.ns.is_none() => {
+            if sym.name == "%" {
+                sym.name = "%1".into();
+            }
+            if sym.name == "%&" {
+                *rest = true;
+            } else if let Some(n) = sym.name.strip_prefix('%').and_then(|n| n.parse().ok()) {
+                *arity = cmp::max(*arity, n);
+            }
+            Expr::Sym(sym)
+        }
+        Expr::List(list) => {
+            let items = list.items.into_iter().map(|item| anon_fn_args(item, arity, rest)).collect::<Result<_, _>>()?;
+            Expr::List(List { span: list.span, meta: list.meta, ..List::new(items) })
+        }
+        Expr::Vector(vec) => {
+            let items = vec.items.into_iter().map(|item| anon_fn_args(item, arity, rest)).collect::<Result<_, _>>()?;
+            Expr::Vector(Vector { items, span: vec.span, meta: vec.meta })
+        }
+        Expr::Map(map) => {
+            let mut pairs = Vec::new();
+            for (key, value) in map.forms().iter() {
+                pairs.push((anon_fn_args(key.clone(), arity, rest)?, anon_fn_args(value.clone(), arity, rest)?));
+            }
+            let mut result = Map::from_forms(pairs).map_err(|err| err.to_string())?;
+            result.span = map.span;
+            result.meta = map.meta;
+            Expr::Map(result)
+        }
+        Expr::Set(set) => {
+            let items = set.iter().map(|item| anon_fn_args(item.to_expr(), arity, rest)).collect::<Result<_, _>>()?;
+            let mut result = set_items(items)?;
+            result.span = set.span;
+            result.meta = set.meta;
+            Expr::Set(result)
+        }
+        _ => expr,
+    })
 }
 
-fn vector<I>(input: I) -> ParseResult<Expr, I>
+fn vector<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
 where
-    I: Stream<Item = Token>,
+    I: Stream<Item = Token, Position = Span>,
 {
-    try(between(
+    (
+        position(),
         token(Token::LBracket),
+        parser(move |input| exprs(input, in_anon_fn)),
+        position(),
         token(Token::RBracket),
-        many(parser(expr)).map(Vector).map(Expr::Vector),
-    ))
+    )
+    .map(|(start, _, items, end, _): (Span, _, _, Span, _)| Expr::Vector(Vector::new(items).with_span(start.to(&end))))
     .parse_stream(input)
 }
 
+fn map<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    (
+        position(),
+        token(Token::LBrace),
+        parser(move |input| exprs(input, in_anon_fn)),
+        position(),
+        token(Token::RBrace),
+    )
+    .and_then(|(start, _, items, end, _): (Span, _, _, Span, _)| {
+        map_entries(items)
+            .map(|map| Expr::Map(map.with_span(start.to(&end))))
+            .map_err(|msg| Error::Message(Info::Owned(msg)))
+    })
+    .parse_stream(input)
+}
+
+fn map_entries(items: Vec<Expr>) -> Result<Map, String> {
+    if !items.len().is_multiple_of(2) {
+        return Err(odd_forms("map literal"));
+    }
+
+    let mut items = items.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+        pairs.push((key, value));
+    }
+    Map::from_forms(pairs).map_err(|err| err.to_string())
+}
+
+fn set<I>(input: I, in_anon_fn: bool) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token, Position = Span>,
+{
+    (
+        position(),
+        token(Token::Set),
+        parser(move |input| exprs(input, in_anon_fn)),
+        position(),
+        token(Token::RBrace),
+    )
+    .and_then(|(start, _, items, end, _): (Span, _, _, Span, _)| {
+        set_items(items)
+            .map(|set| Expr::Set(set.with_span(start.to(&end))))
+            .map_err(|msg| Error::Message(Info::Owned(msg)))
+    })
+    .parse_stream(input)
+}
+
+fn set_items(items: Vec<Expr>) -> Result<Set, String> {
+    let mut set = Set::new();
+    for item in items {
+        let key = Key::try_from(&item).map_err(|err| err.to_string())?;
+        if !set.insert(key.clone()) {
+            return Err(format!("duplicate item in set literal: {}", key));
+        }
+    }
+    Ok(set)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use quickcheck::{Arbitrary, Gen};
+    use {lexer, ops};
+    use span::Position;
+    use stream::{StringStream, TokenStream};
+    use util::testing::*;
+
+    // A namespace and name which should read as a single symbol
+    #[derive(Clone, Debug)]
+    struct SymbolParts(Option<String>, String);
+
+    impl SymbolParts {
+        fn text(&self) -> String {
+            match self.0 {
+                Some(ref ns) => format!("{}/{}", ns, self.1),
+                None => self.1.clone(),
+            }
+        }
+    }
+
+    fn symbol_name<G: Gen>(g: &mut G) -> String {
+        const START: &[char] = &[
+            'a', 'z', 'A', 'Q', 'é', 'λ', '_', '+', '-', '*', '=', '<', '>', '!', '?', '%', '&', '$', '.',
+        ];
+        const BODY: &[char] = &['a', 'z', 'é', '0', '9', '_', '-', '!', '?', '.', '$', '>'];
+
+        let mut name = vec![*g.choose(START).unwrap()];
+        let len = g.gen_range(0, 8);
+        name.extend((0..len).map(|_| *g.choose(BODY).unwrap()));
+
+        // Keep things like -1 and .5 from reading as numbers
+        if "+-.".contains(name[0]) && name.get(1).is_some_and(|&c| c == '.' || c.is_ascii_digit()) {
+            name[1] = 'a';
+        }
+        name.into_iter().collect()
+    }
+
+    impl Arbitrary for SymbolParts {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            match g.gen_range(0, 10) {
+                0 => SymbolParts(None, "/".into()),
+                1..=4 => SymbolParts(Some(symbol_name(g)), symbol_name(g)),
+                _ => SymbolParts(None, symbol_name(g)),
+            }
+        }
+    }
+
+    quickcheck! {
+        fn symbol_round_trip(parts: SymbolParts) -> bool {
+            let text = parts.text();
+            let (tokens, _) = lexer::lex(StringStream::new(&text)).unwrap();
+            let (exprs, _) = parse(TokenStream::new(tokens.into_iter())).unwrap();
+            let expected = Symbol { ns: parts.0, ..Symbol::new(parts.1) };
+            exprs == vec![Expr::from(expected)] && exprs[0].to_string() == text
+        }
+    }
+
+    fn parse_tokens(tokens: Vec<Token>) -> Result<Vec<Expr>, ParseError<TokenStream>> {
+        let spanned = tokens.into_iter().map(|token| (token, Span::default()));
+        parse(TokenStream::new(spanned)).map(|(exprs, _)| exprs)
+    }
 
     #[test]
     fn empty_list() {
         let input = vec![Token::LParen, Token::RParen];
-        let output = vec![Expr::List(List(Vec::new()))];
-        let empty: &[Token] = &[];
-        assert_eq!(
-            Ok((output, empty)),
-            parse(&*input)
-        );
+        let output = vec![Expr::List(List::new(Vec::new()))];
+        assert_eq!(Ok(output), parse_tokens(input));
     }
 
     #[test]
     fn empty_vector() {
         let input = vec![Token::LBracket, Token::RBracket];
-        let output = vec![Expr::Vector(Vector(Vec::new()))];
-        let empty: &[Token] = &[];
+        let output = vec![Expr::Vector(Vector::new(Vec::new()))];
+        assert_eq!(Ok(output), parse_tokens(input));
+    }
+
+    #[test]
+    fn discard() {
+        let input = vec![
+            Token::Discard, Token::LParen, Token::RParen,
+            Token::LBracket, Token::from(1), Token::Discard, Token::from(2), Token::RBracket,
+        ];
+        let output = vec![Expr::Vector(Vector::new(vec![Expr::from(1)]))];
+        assert_eq!(Ok(output), parse_tokens(input));
+    }
+
+    #[test]
+    fn nested_discard() {
+        let input = vec![
+            Token::Discard, Token::Discard, Token::from(1), Token::from(2), Token::from(3),
+        ];
+        let output = vec![Expr::from(3)];
+        assert_eq!(Ok(output), parse_tokens(input));
+    }
+
+    #[test]
+    fn spans() {
+        let (tokens, _) = lexer::lex(StringStream::new("(f\n  [x])")).unwrap();
+        let (exprs, _) = parse(TokenStream::new(tokens.into_iter())).unwrap();
+        let list = exprs[0].list().unwrap();
+        let span = |start, end| Some(Span::new(None, start, end));
+
+        assert_eq!(span(Position::new(1, 1), Position::new(2, 7)), list.span);
+        assert_eq!(span(Position::new(1, 2), Position::new(1, 3)), list.items[0].sym().unwrap().span);
+        assert_eq!(span(Position::new(2, 3), Position::new(2, 6)), list.items[1].vector().unwrap().span);
+
+        // Literals and keywords keep theirs too
+        let (tokens, _) = lexer::lex(StringStream::new("[1 2.5 \"a\" \\b #t :k]")).unwrap();
+        let (exprs, _) = parse(TokenStream::new(tokens.into_iter())).unwrap();
+        let columns = exprs[0].vector().unwrap().items.iter()
+            .map(|item| item.span().map(|span| (span.start.column, span.end.column)))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Some((2, 3)), Some((4, 7)), Some((8, 11)), Some((12, 14)), Some((15, 17)), Some((18, 20))], columns);
+    }
+
+    #[test]
+    fn quasiquote() {
+        let input = vec![
+            Token::Quasiquote, Token::LParen,
+            Token::Unquote, Token::from(1), Token::UnquoteSplicing, Token::from(2),
+            Token::RParen,
+        ];
+        let form = |name, expr| Expr::List(List::new(vec![Expr::from(Symbol::new(name)), expr]));
+        let output = vec![form("quasiquote", Expr::List(List::new(vec![
+            form("unquote", Expr::from(1)),
+            form("unquote-splicing", Expr::from(2)),
+        ])))];
+        assert_eq!(Ok(output), parse_tokens(input));
+    }
+
+    #[test]
+    fn anon_fn() {
+        let (tokens, _) = lexer::lex(StringStream::new("#(+ % %3 [%&])")).unwrap();
+        let (exprs, _) = parse(TokenStream::new(tokens.into_iter())).unwrap();
+        assert_eq!("(fn [%1 %2 %3 & %&] (+ %1 %3 [%&]))", exprs[0].to_string());
+
+        let (tokens, _) = lexer::lex(StringStream::new("#(contains? #{%} 1)")).unwrap();
+        let (exprs, _) = parse(TokenStream::new(tokens.into_iter())).unwrap();
+        assert_eq!("(fn [%1] (contains? #{%1} 1))", exprs[0].to_string());
+
+        let (tokens, _) = lexer::lex(StringStream::new("#(map #(+ % 1) %)")).unwrap();
+        let err = parse(TokenStream::new(tokens.into_iter())).unwrap_err();
+        assert_eq!(Position::new(1, 7), err.position.start);
+        assert!(err.to_string().contains("nested #() is not allowed"));
+    }
+
+    #[test]
+    fn anon_fn_calls() {
+        let printed = eval_printed("(def add #(+ % %2)) (add 1 2) (def pi #(do 3)) (pi)
+            (def all #(do %&)) (all) (all 1 2) (def second #(list %2 %&)) (second 1 2 3 4)
+            (def one? #(contains? #{%} 1)) (one? 1)", &ops::env());
+        assert_eq!(vec!["3", "3"], vec![&printed[1], &printed[3]]);
+        assert_eq!(vec!["()", "(1 2)", "second", "(2 (3 4))", "one?", "#t"], printed[5..].to_vec());
+    }
+
+    #[test]
+    fn map() {
+        let input = vec![Token::LBrace, Token::Keyword("a".into()), Token::from(1), Token::RBrace];
+        let mut map = Map::new();
+        map.insert(Key::Keyword(Keyword::new("a")), Expr::from(1));
+        assert_eq!(Ok(vec![Expr::Map(map)]), parse_tokens(input));
+    }
+
+    #[test]
+    fn set() {
+        let input = vec![Token::Set, Token::from(1), Token::Keyword("a".into()), Token::RBrace];
+        let mut set = Set::new();
+        set.insert(Key::Int(1));
+        set.insert(Key::Keyword(Keyword::new("a")));
+        assert_eq!(Ok(vec![Expr::Set(set)]), parse_tokens(input));
+
+        let input = vec![Token::Set, Token::from(1), Token::from(1), Token::RBrace];
+        assert!(parse_tokens(input).unwrap_err().to_string().contains("duplicate item in set literal: 1"));
+    }
+
+    #[test]
+    fn meta() {
+        let (tokens, _) = lexer::lex(StringStream::new("^:a ^{:b 2} [x] ^T y")).unwrap();
+        let (exprs, _) = parse(TokenStream::new(tokens.into_iter())).unwrap();
+        assert_eq!(Expr::Vector(Vector::new(vec![Expr::from(Symbol::new("x"))])), exprs[0]);
+        assert_eq!("{:a #t :b 2}", exprs[0].meta().unwrap().to_string());
+        assert_eq!("{:tag T}", exprs[1].meta().unwrap().to_string());
+
+        let (tokens, _) = lexer::lex(StringStream::new("^:a 1")).unwrap();
+        let err = parse(TokenStream::new(tokens.into_iter())).unwrap_err();
+        assert!(err.to_string().contains("cannot attach metadata to: 1"));
+    }
+
+    #[test]
+    fn parse_all() {
+        let source = "(a ]\n(b)\n{:k}\n)\n[c (d\n";
+        let (tokens, _) = lexer::lex(StringStream::new(source)).unwrap();
+        let (exprs, diagnostics) = super::parse_all(&tokens);
+
+        assert_eq!(vec!["(b)"], exprs.iter().map(Expr::to_string).collect::<Vec<_>>());
         assert_eq!(
-            Ok((output, empty)),
-            parse(&*input)
+            vec![
+                "1:1: unclosed `(`: expected `)`, found `]` at 1:4",
+                "3:1: expected an even number of forms in map literal",
+                "4:1: unmatched `)`",
+                "5:4: unclosed `(`: expected `)`, found end of input",
+            ],
+            diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>()
         );
     }
-}
\ No newline at end of file
+
+    // Each form is parsed without copying the tokens after it, so this is quick
+    #[test]
+    fn parse_all_many_forms() {
+        // Every 500th form is a map missing its value
+        let source = (0..5000)
+            .map(|i| if i % 500 == 0 { "{:k}\n" } else { "(def x [1 2.5 {:a \"b\"} #{c}]) ; x\n" })
+            .collect::<String>();
+        let (tokens, _) = lexer::lex(StringStream::new(&source)).unwrap();
+        let (exprs, diagnostics) = super::parse_all(&tokens);
+
+        assert_eq!(4990, exprs.len());
+        let lines = diagnostics.iter().map(|diagnostic| diagnostic.span.start.line).collect::<Vec<_>>();
+        assert_eq!((0..10).map(|i| i * 500 + 1).collect::<Vec<_>>(), lines);
+    }
+
+    #[test]
+    fn map_odd_entries() {
+        let (tokens, _) = lexer::lex(StringStream::new("(f\n {:a 1 :b})")).unwrap();
+        let err = parse(TokenStream::new(tokens.into_iter())).unwrap_err();
+        assert_eq!(Position::new(2, 2), err.position.start);
+        assert!(err.to_string().contains("expected an even number of forms in map literal"));
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::env;
+use std::iter;
+use itertools::Itertools;
+use term_size;
+
+use types::Expr;
+
+// A document which lays itself out to fit a width, after Wadler's "A prettier printer"
+#[derive(Debug)]
+pub enum Doc {
+    Text(String),
+    // A space, or a newline if the group around it doesn't fit on one line
+    Line,
+    // Always a newline, e.g. after a comment, so the group around it never fits
+    HardLine,
+    Concat(Vec<Doc>),
+    // Indent lines inside by some more than the enclosing lines
+    Nest(usize, Box<Doc>),
+    // Indent lines inside to the column this starts at
+    Align(Box<Doc>),
+    // Either every line inside breaks, or none do
+    Group(Box<Doc>),
+}
+
+pub fn text<S: Into<String>>(s: S) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn group(docs: Vec<Doc>) -> Doc {
+    Doc::Group(Box::new(Doc::Concat(docs)))
+}
+
+impl Doc {
+    // Add another doc after this one
+    pub fn push(&mut self, doc: Doc) {
+        match *self {
+            Doc::Concat(ref mut docs) => docs.push(doc),
+            _ => {
+                let first = ::std::mem::replace(self, Doc::Concat(Vec::new()));
+                *self = Doc::Concat(vec![first, doc]);
+            }
+        }
+    }
+
+    // Whether this finishes with a newline already, e.g. after a comment
+    fn ends_line(&self) -> bool {
+        match *self {
+            Doc::HardLine => true,
+            Doc::Concat(ref docs) => docs.last().is_some_and(Doc::ends_line),
+            Doc::Nest(_, ref doc) | Doc::Align(ref doc) | Doc::Group(ref doc) => doc.ends_line(),
+            _ => false,
+        }
+    }
+}
+
+// Docs with `sep` between them, except after one which already ends its line
+fn join(docs: Vec<Doc>, sep: fn() -> Doc) -> Vec<Doc> {
+    let mut joined: Vec<Doc> = Vec::with_capacity(docs.len() * 2);
+    for doc in docs {
+        if joined.last().is_some_and(|last| !last.ends_line()) {
+            joined.push(sep());
+        }
+        joined.push(doc);
+    }
+    joined
+}
+
+fn line() -> Doc {
+    Doc::Line
+}
+
+fn space() -> Doc {
+    text(" ")
+}
+
+// Data lines up under its first item:
+//
+//   [{:a 1}
+//    {:b 2}]
+fn delimited(open: Doc, docs: Vec<Doc>, close: Doc) -> Doc {
+    group(vec![open, Doc::Align(Box::new(Doc::Concat(join(docs, line)))), close])
+}
+
+// Something to print, whether a value or source code, before the rules lay it out
+#[derive(Debug)]
+pub enum Tree {
+    // Printed as it is, with the name of an unqualified symbol for the rules to look up
+    Atom(Doc, Option<String>),
+    // 'x, #_x or ^meta x
+    Prefix(Doc, Vec<Tree>),
+    // Items between an opening and closing delimiter
+    Form(Doc, Vec<Tree>, Doc, Shape),
+    // A tree with comments on the lines before it and one after it on the same
+    // line, which don't count towards whether the tree fits
+    Commented(Doc, Box<Tree>, Doc),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    // A call or special form, e.g. (f x) or (let [x 1] x)
+    Code,
+    // Items lined up under the first, e.g. [1 2]
+    Data,
+    // Pairs of items, e.g. {:a 1}
+    Pairs,
+}
+
+impl Tree {
+    fn symbol(&self) -> Option<&str> {
+        match *self {
+            Tree::Atom(_, ref symbol) => symbol.as_ref().map(String::as_str),
+            Tree::Commented(_, ref tree, _) => tree.symbol(),
+            _ => None,
+        }
+    }
+
+    // Whether this has comments on the lines before it
+    fn commented_before(&self) -> bool {
+        matches!(*self, Tree::Commented(ref before, _, _) if before.ends_line())
+    }
+
+    // Whether this has a comment after it on the same line
+    fn commented_after(&self) -> bool {
+        matches!(*self, Tree::Commented(_, _, ref after) if after.ends_line())
+    }
+
+    // A vector laid out in pairs instead, e.g. the bindings of a let, even with comments around it
+    fn into_pairs(self) -> Tree {
+        match self {
+            Tree::Form(open, items, close, Shape::Data) => Tree::Form(open, items, close, Shape::Pairs),
+            Tree::Commented(before, tree, after) => Tree::Commented(before, Box::new(tree.into_pairs()), after),
+            tree => tree,
+        }
+    }
+
+    // Add a doc after the end of this tree, e.g. a comment on the same line
+    pub fn push(&mut self, doc: Doc) {
+        match *self {
+            Tree::Commented(_, _, ref mut after) => after.push(doc),
+            _ => {
+                let tree = ::std::mem::replace(self, Tree::Atom(Doc::Concat(Vec::new()), None));
+                *self = Tree::Commented(Doc::Concat(Vec::new()), Box::new(tree), doc);
+            }
+        }
+    }
+}
+
+// Forms which bind names in a vector of pairs, e.g. (let [x 1 y 2] ...)
+const BINDING_FORMS: &[&str] = &["let", "loop"];
+
+// Forms which may be named before their parameters, e.g. (fn name [x] ...)
+const NAMED_FORMS: &[&str] = &["fn", "macro"];
+
+pub struct Printer {
+    width: usize,
+    indent: usize,
+    // How many arguments of a form stay on its first line, before a body which is
+    // indented like code rather than lined up like data
+    bodies: HashMap<String, usize>,
+}
+
+impl Printer {
+    pub fn new(width: usize) -> Self {
+        let bodies = [("def", 1), ("if", 1), ("let", 1), ("loop", 1), ("do", 0), ("fn", 1), ("macro", 1)];
+        Printer {
+            width,
+            indent: 2,
+            bodies: bodies.iter().map(|&(form, args)| (form.to_owned(), args)).collect(),
+        }
+    }
+
+    pub fn with_indent(self, indent: usize) -> Self {
+        Printer { indent, ..self }
+    }
+
+    // Indent the body of `form` like code, after its first `args` arguments
+    pub fn with_body(mut self, form: &str, args: usize) -> Self {
+        self.bodies.insert(form.to_owned(), args);
+        self
+    }
+
+    pub fn print(&self, expr: &Expr) -> String {
+        self.render(tree(expr))
+    }
+
+    pub fn render(&self, tree: Tree) -> String {
+        layout(&self.doc(tree), self.width)
+    }
+
+    fn doc(&self, tree: Tree) -> Doc {
+        match tree {
+            Tree::Atom(doc, _) => doc,
+            Tree::Prefix(prefix, trees) => {
+                let mut docs = vec![prefix];
+                docs.extend(join(trees.into_iter().map(|tree| self.doc(tree)).collect(), space));
+                Doc::Concat(docs)
+            }
+            Tree::Form(open, items, close, Shape::Code) => self.form(open, items, close),
+            Tree::Form(open, items, close, Shape::Data) => {
+                delimited(open, items.into_iter().map(|item| self.doc(item)).collect(), close)
+            }
+            Tree::Form(open, items, close, Shape::Pairs) => delimited(open, self.pairs(items), close),
+            Tree::Commented(before, tree, after) => Doc::Concat(vec![before, self.doc(*tree), after]),
+        }
+    }
+
+    // A key and value, or a name and binding, with the second under the first if need be
+    fn pairs(&self, items: Vec<Tree>) -> Vec<Doc> {
+        let mut items = items.into_iter();
+        let mut pairs = Vec::new();
+        while let Some(first) = items.next() {
+            pairs.push(match items.next() {
+                // A comment after the pair needn't break it
+                Some(Tree::Commented(before, second, after)) => {
+                    let second = Tree::Commented(before, second, Doc::Concat(Vec::new()));
+                    Doc::Concat(vec![self.pair(first, second), after])
+                }
+                Some(second) => self.pair(first, second),
+                None => self.doc(first),
+            });
+        }
+        pairs
+    }
+
+    fn pair(&self, first: Tree, second: Tree) -> Doc {
+        let docs = join(vec![self.doc(first), self.doc(second)], line);
+        group(vec![Doc::Nest(self.indent, Box::new(Doc::Concat(docs)))])
+    }
+
+    fn form(&self, open: Doc, mut items: Vec<Tree>, close: Doc) -> Doc {
+        let head = items.first().and_then(Tree::symbol).map(str::to_owned);
+
+        match head.as_ref().and_then(|head| self.bodies.get(head).map(|&args| (head, args))) {
+            // (let [x 1]
+            //   body)
+            Some((name, mut args)) => {
+                if NAMED_FORMS.contains(&name.as_str()) {
+                    if items.get(1).and_then(Tree::symbol).is_some() {
+                        args += 1;
+                    }
+                    // A clause for each arity goes in the body, (fn name ([] ...) ([x] ...))
+                    if let Some(&Tree::Form(_, _, _, Shape::Code)) = items.get(args) {
+                        args -= 1;
+                    }
+                }
+                let body = items.split_off(usize::min(args + 1, items.len()));
+                let header = items.into_iter().enumerate().map(|(i, item)| {
+                    if i == 1 && BINDING_FORMS.contains(&name.as_str()) {
+                        self.doc(item.into_pairs())
+                    } else {
+                        self.doc(item)
+                    }
+                });
+
+                // The header is nested too, so that a comment in it ends its line at
+                // the body's indent
+                let mut lines = join(header.collect(), space);
+                let body: Vec<_> = body.into_iter().map(|item| self.doc(item)).collect();
+                if !body.is_empty() {
+                    if !lines.last().is_some_and(Doc::ends_line) {
+                        lines.push(Doc::Line);
+                    }
+                    lines.extend(join(body, line));
+                }
+                group(vec![open, Doc::Nest(self.indent, Box::new(Doc::Concat(lines))), close])
+            }
+            // (f ; comment
+            //   a)
+            None if head.is_some() && items.len() > 1 && (items[0].commented_after() || items[1].commented_before()) => {
+                let items = items.into_iter().map(|item| self.doc(item)).collect();
+                group(vec![open, Doc::Nest(self.indent, Box::new(Doc::Concat(join(items, line)))), close])
+            }
+            // (f a
+            //    b)
+            None if head.is_some() && items.len() > 1 => {
+                let mut items = items.into_iter().map(|item| self.doc(item));
+                let mut docs = vec![open];
+                docs.extend(items.next());
+                docs.push(space());
+                docs.push(Doc::Align(Box::new(Doc::Concat(join(items.collect(), line)))));
+                docs.push(close);
+                group(docs)
+            }
+            _ => delimited(open, items.into_iter().map(|item| self.doc(item)).collect(), close),
+        }
+    }
+}
+
+// Values print like code, with their maps and sets in the same order as Display
+fn tree(expr: &Expr) -> Tree {
+    let form = |open: &str, items: Vec<Tree>, close: &str, shape| Tree::Form(text(open), items, text(close), shape);
+    match *expr {
+        Expr::List(ref list) if !list.items.is_empty() => form("(", list.items.iter().map(tree).collect(), ")", Shape::Code),
+        Expr::Vector(ref vector) => form("[", vector.items.iter().map(tree).collect(), "]", Shape::Data),
+        Expr::Map(ref map) => {
+            let items = map.iter()
+                .map(|(key, value)| (format!("{} {}", key, value), key, value))
+                .sorted_by(|a, b| a.0.cmp(&b.0))
+                .into_iter()
+                .flat_map(|(_, key, value)| vec![tree(&key.to_expr()), tree(value)])
+                .collect();
+            form("{", items, "}", Shape::Pairs)
+        }
+        Expr::Set(ref set) => {
+            let items = set.iter()
+                .map(|key| key.to_expr())
+                .sorted_by(|a, b| a.to_string().cmp(&b.to_string()));
+            form("#{", items.iter().map(tree).collect(), "}", Shape::Data)
+        }
+        Expr::Sym(ref sym) if sym.ns.is_none() => Tree::Atom(text(expr.to_string()), Some(sym.name.clone())),
+        _ => Tree::Atom(text(expr.to_string()), None),
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Printer::new(terminal_width())
+    }
+}
+
+// The width of the terminal, or of $COLUMNS if the output isn't a terminal and
+// the shell exports it, or else the usual 80 columns
+pub fn terminal_width() -> usize {
+    term_size::dimensions().map(|(width, _)| width)
+        .or_else(|| env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()))
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+}
+
+fn layout(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    // What's left to lay out, as (indent, flat, doc), with the next last
+    let mut rest = vec![(0, false, doc)];
+
+    while let Some((indent, flat, doc)) = rest.pop() {
+        match *doc {
+            Doc::Text(ref s) => {
+                out.push_str(s);
+                column += s.chars().count();
+            }
+            Doc::Line if flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::Line | Doc::HardLine => {
+                out.push('\n');
+                out.extend(iter::repeat_n(' ', indent));
+                column = indent;
+            }
+            Doc::Concat(ref docs) => rest.extend(docs.iter().rev().map(|doc| (indent, flat, doc))),
+            Doc::Nest(more, ref doc) => rest.push((indent + more, flat, doc)),
+            Doc::Align(ref doc) => rest.push((column, flat, doc)),
+            Doc::Group(ref doc) => {
+                let flat = flat || fits(width as isize - column as isize, doc, &rest);
+                rest.push((indent, flat, doc));
+            }
+        }
+    }
+    out
+}
+
+// Whether a group fits on one line, along with whatever follows it up to the next line break
+fn fits(mut room: isize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut docs = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+
+    while room >= 0 {
+        let (flat, doc) = match docs.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, flat, doc)) => (flat, doc),
+                None => return true,
+            },
+        };
+        match *doc {
+            Doc::Text(ref s) => room -= s.chars().count() as isize,
+            Doc::Line if flat => room -= 1,
+            Doc::Line => return true,
+            Doc::HardLine => return !flat,
+            Doc::Concat(ref inner) => docs.extend(inner.iter().rev().map(|doc| (flat, doc))),
+            Doc::Nest(_, ref doc) | Doc::Align(ref doc) | Doc::Group(ref doc) => docs.push((flat, doc)),
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use input;
+
+    fn pprint(source: &str, width: usize) -> String {
+        let exprs = input::read_all(source.as_bytes(), None).unwrap();
+        Printer::new(width).print(&exprs[0])
+    }
+
+    #[test]
+    fn data() {
+        let source = "[{:name \"one\" :tags #{:a :b}} {:name \"two\" :tags #{}} (1 2 3)]";
+        assert_eq!(source, pprint(source, 80));
+        assert_eq!(
+            "[{:name \"one\" :tags #{:a :b}}\n {:name \"two\" :tags #{}}\n (1 2 3)]",
+            pprint(source, 40)
+        );
+        assert_eq!(
+            "[{:name \"one\"\n  :tags #{:a :b}}\n {:name \"two\"\n  :tags #{}}\n (1 2 3)]",
+            pprint(source, 20)
+        );
+        assert_eq!("{:key\n   [1\n    2]}", pprint("{:key [1 2]}", 4));
+    }
+
+    #[test]
+    fn code() {
+        let source = "(def f (fn f [x y] (let [a (+ x 1) b (* y 2)] (if (< a b) (list a b) (list b a)))))";
+        assert_eq!(source, pprint(source, 100));
+        assert_eq!(
+            "(def f
+  (fn f [x y]
+    (let [a (+ x 1) b (* y 2)]
+      (if (< a b) (list a b) (list b a)))))",
+            pprint(source, 45)
+        );
+        assert_eq!(
+            "(def f
+  (fn f [x y]
+    (let [a (+ x 1) b (* y 2)]
+      (if (< a b)
+        (list a b)
+        (list b a)))))",
+            pprint(source, 40)
+        );
+        assert_eq!(
+            "(def f
+  (fn f [x y]
+    (let [a (+ x 1)
+          b (* y 2)]
+      (if (< a b)
+        (list a b)
+        (list b a)))))",
+            pprint(source, 25)
+        );
+        assert_eq!("(some-function arg-one\n               arg-two)", pprint("(some-function arg-one arg-two)", 30));
+        assert_eq!(
+            "(loop [i 0 acc []]
+  (if (< i 3)
+    (recur (+ i 1) (conj acc i))
+    acc))",
+            pprint("(loop [i 0 acc []] (if (< i 3) (recur (+ i 1) (conj acc i)) acc))", 40)
+        );
+        assert_eq!(
+            "(fn greet\n  ([] (greet \"world\"))\n  ([n] (str \"hello \" n)))",
+            pprint("(fn greet ([] (greet \"world\")) ([n] (str \"hello \" n)))", 40)
+        );
+    }
+
+    #[test]
+    fn rules() {
+        let source = "(when-ok (check x) (print x) (done))";
+        assert_eq!("(when-ok (check x)\n         (print x)\n         (done))", pprint(source, 20));
+        let printer = Printer::new(20).with_indent(4).with_body("when-ok", 1);
+        let exprs = input::read_all(source.as_bytes(), None).unwrap();
+        assert_eq!("(when-ok (check x)\n    (print x)\n    (done))", printer.print(&exprs[0]));
+    }
+}
@@ -0,0 +1,370 @@
+use std::io::BufRead;
+use std::mem;
+use std::sync::Arc;
+
+use combine::{parser, Parser, StreamOnce};
+
+use diagnostic::Diagnostic;
+use error::*;
+use lexer::{self, SpannedTokens};
+use parser::{parse_all, parse_form};
+use span::{Position, Span};
+use stream::{self, StringStream, TokenStream};
+use token::Token;
+use types::Expr;
+
+// Reads top-level forms one at a time from any buffered input, pulling in more
+// only while the form so far is unfinished. Tokens are kept between reads, so
+// each character is lexed once however many lines a form spans, and each token
+// is parsed once however many forms share a line.
+pub struct Reader<B> {
+    input: B,
+    // Text which has been read but not lexed yet, starting at `position`. The
+    // first `scanned` bytes of it are in a string or block comment which ran past
+    // the end of a line, and which can't end before a later quote or |#.
+    text: String,
+    position: Span,
+    scanned: usize,
+    // Whole forms which have been lexed but not parsed yet
+    forms: TokenStream,
+    // Tokens lexed after them. The first `complete` of them are whole forms too,
+    // and the rest leave forms open which need `closers`.
+    tokens: SpannedTokens,
+    complete: usize,
+    closers: Vec<Token>,
+    // Bad tokens which were skipped in forms which aren't finished yet
+    errors: Vec<Diagnostic>,
+    done: bool,
+}
+
+impl<B: BufRead> Reader<B> {
+    pub fn new(input: B, file: Option<&str>) -> Self {
+        Reader {
+            input,
+            text: String::new(),
+            position: Span::point(file.map(Arc::from), Position::new(1, 1)),
+            scanned: 0,
+            forms: TokenStream::default(),
+            tokens: Vec::new(),
+            complete: 0,
+            closers: Vec::new(),
+            errors: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.input
+    }
+
+    // Read the next form, pulling in as much input as it takes. Returns None at the
+    // end of the input, or an incomplete error if it ends part way through a form.
+    pub fn read(&mut self) -> Result<Option<Expr>> {
+        loop {
+            let result = self.read_buffered();
+            let wants_more = match result {
+                Ok(ref expr) => expr.is_none(),
+                Err(ref err) => err.is_incomplete(),
+            };
+            if !wants_more || self.done {
+                return result;
+            }
+            self.fill()?;
+        }
+    }
+
+    // Read the next form from the input buffered so far, without waiting for more.
+    // Returns None if there is nothing left, or an incomplete error if there is only
+    // part of a form, in which case `fill` and try again.
+    pub fn read_buffered(&mut self) -> Result<Option<Expr>> {
+        self.lex()?;
+
+        // Forms after a bad token are reported with it rather than handed out
+        if self.complete > 0 && self.errors.is_empty() {
+            // Usually nothing is left of the forms before, unless e.g. a quote's form
+            // was on the next line
+            let mut forms = self.forms.rest().to_vec();
+            forms.extend(self.tokens.drain(..self.complete));
+            self.forms = TokenStream::shared(forms.into());
+            self.complete = 0;
+        }
+
+        while self.forms.remaining() > 0 {
+            match parse_form(self.forms.clone()) {
+                Ok((expr, rest)) => {
+                    self.forms = rest;
+                    if expr.is_some() {
+                        return Ok(expr);
+                    }
+                }
+                // e.g. a quote whose form is on the next line
+                Err(ref err) if stream::is_incomplete(err) => break,
+                Err(_) => {
+                    let (_, diagnostics) = parse_all(self.forms.rest());
+                    self.clear();
+                    return Err(ErrorKind::Syntax(diagnostics).into());
+                }
+            }
+        }
+
+        if !self.errors.is_empty() && (self.closers.is_empty() || self.done) {
+            return Err(self.report_errors());
+        }
+
+        if self.forms.remaining() == 0 && self.tokens.is_empty() && self.text.is_empty() {
+            Ok(None)
+        } else {
+            let err = ErrorKind::Incomplete(self.start()).into();
+            // Nothing more is coming to finish the form, so don't report it again
+            if self.done {
+                self.clear();
+            }
+            Err(err)
+        }
+    }
+
+    // Read another line of input, returning false at the end of the input
+    pub fn fill(&mut self) -> Result<bool> {
+        self.done = self.input.read_line(&mut self.text)? == 0;
+        Ok(!self.done)
+    }
+
+    // Lex as many whole tokens as the buffered text holds
+    fn lex(&mut self) -> Result<()> {
+        let unscanned = &self.text[self.scanned..];
+        if self.scanned > 0 && !self.done && !unscanned.contains('"') && !unscanned.contains("|#") {
+            self.scanned = self.text.len();
+            return Ok(());
+        }
+        self.scanned = 0;
+
+        let mut input = StringStream::resume(&self.text, self.position.clone());
+        let result = loop {
+            input = match parser(lexer::whitespace).parse(input.clone()) {
+                Ok((_, rest)) => rest,
+                Err(err) => break Err(err),
+            };
+            if input.clone().uncons().is_err() {
+                break Ok(());
+            }
+
+            match parser(lexer::spanned_token).parse(input.clone()) {
+                // A token which runs to the end of the text might carry on in the next line
+                Ok((_, ref rest)) if !self.done && rest.clone().uncons().is_err() => break Ok(()),
+                Ok(((token, span), rest)) => {
+                    self.push(token, span);
+                    input = rest;
+                }
+                Err(err) => {
+                    if stream::is_incomplete(&err) {
+                        break Err(err);
+                    }
+                    // Hand out the forms before a bad token first, and come back to it
+                    if self.complete > 0 && self.errors.is_empty() {
+                        break Ok(());
+                    }
+                    // Skip it and carry on, to report it with any other errors in its form
+                    let (diagnostic, rest) = lexer::recover(&err, input);
+                    self.errors.push(diagnostic);
+                    input = rest;
+                }
+            }
+        };
+
+        self.text = input.remainder();
+        self.position = input.position();
+
+        // Only a string or block comment can be unfinished at the end of a line
+        if let Err(ref err) = result {
+            if stream::is_incomplete(err) && self.text.ends_with('\n') {
+                self.scanned = self.text.len();
+            }
+        }
+
+        // The input ended in a string or block comment, but hand out the forms
+        // before it first, and report it when we get to it
+        if result.is_err() && self.done && self.complete == 0 && self.errors.is_empty() {
+            let err = ErrorKind::Incomplete(self.start()).into();
+            self.clear();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // Report bad tokens along with any other errors in the forms they were in,
+    // once those forms are finished, dropping the forms
+    fn report_errors(&mut self) -> Error {
+        let mut tokens = self.forms.rest().to_vec();
+        tokens.append(&mut self.tokens);
+        let (_, found) = parse_all(&tokens);
+
+        let mut diagnostics = mem::take(&mut self.errors);
+        diagnostics.extend(found);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+
+        self.forms = TokenStream::default();
+        self.complete = 0;
+        self.closers.clear();
+        ErrorKind::Syntax(diagnostics).into()
+    }
+
+    fn push(&mut self, token: Token, span: Span) {
+        if let Some(closer) = token.closer() {
+            self.closers.push(closer);
+        } else if token.is_closer() && self.closers.pop() != Some(token.clone()) {
+            // A mismatched closer won't parse, so don't wait for more before reporting it
+            self.closers.clear();
+        }
+        self.tokens.push((token, span));
+        if self.closers.is_empty() {
+            self.complete = self.tokens.len();
+        }
+    }
+
+    // Where the unfinished form starts
+    fn start(&self) -> Span {
+        let span = self.forms.rest().iter()
+            .chain(&self.tokens)
+            .next()
+            .map_or(&self.position, |(_, span)| span);
+        Span::point(span.file.clone(), span.start)
+    }
+
+    // Throw away everything buffered, e.g. the rest of a line with an error in it
+    fn clear(&mut self) {
+        for c in self.text.chars() {
+            self.position.start.advance(c);
+        }
+        self.position.end = self.position.start;
+        self.text.clear();
+        self.scanned = 0;
+        self.forms = TokenStream::default();
+        self.tokens.clear();
+        self.complete = 0;
+        self.closers.clear();
+        self.errors.clear();
+    }
+}
+
+impl<B: BufRead> Iterator for Reader<B> {
+    type Item = Result<Expr>;
+
+    fn next(&mut self) -> Option<Result<Expr>> {
+        self.read().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_from(text: &str) -> Reader<Cursor<&str>> {
+        Reader::new(Cursor::new(text), Some("test.tele"))
+    }
+
+    #[test]
+    fn multi_line_forms() {
+        let mut reader = read_from("(list \"one\ntwo\" #| a\nb |#\n 3)\n(+ 1 2)");
+
+        let first = reader.read().unwrap().unwrap();
+        assert_eq!("(list \"one\\ntwo\" 3)", first.to_string());
+
+        let second = reader.read().unwrap().unwrap();
+        assert_eq!(5, second.span().unwrap().start.line);
+        assert!(reader.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn forms_on_one_line() {
+        let forms: Vec<_> = read_from("1 #_ 2 'a [b\nc] (d")
+            .map(|form| form.map(|expr| expr.to_string()).map_err(|err| err.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                Ok("1".to_owned()),
+                Ok("(quote a)".to_owned()),
+                Ok("[b c]".to_owned()),
+                Err("test.tele:2:4: incomplete form, expected more input".to_owned()),
+            ],
+            forms
+        );
+    }
+
+    #[test]
+    fn incomplete() {
+        let mut reader = read_from("(+ 1\n");
+        assert!(reader.fill().unwrap());
+        assert!(reader.read_buffered().unwrap_err().is_incomplete());
+
+        // The rest of the form may still come
+        let mut reader = Reader::new(Cursor::new("(+ 1\n2)\n"), None);
+        reader.fill().unwrap();
+        assert!(reader.read_buffered().unwrap_err().is_incomplete());
+        reader.fill().unwrap();
+        assert_eq!("(+ 1 2)", reader.read_buffered().unwrap().unwrap().to_string());
+        assert!(reader.read_buffered().unwrap().is_none());
+
+        for text in &["\"abc", "#| abc", "'", "^:a", "[1 2"] {
+            assert!(read_from(text).read().unwrap_err().is_incomplete(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let mut reader = read_from("(def x 1x) 2\n(+ 1 2))\n(a [1 2)\n(f)");
+
+        let err = reader.read().unwrap_err();
+        assert!(!err.is_incomplete());
+        assert_eq!("test.tele:1:8: unexpected `1x`", err.to_string());
+
+        // The rest of the line is dropped, but the next one is still read
+        assert_eq!("(+ 1 2)", reader.read().unwrap().unwrap().to_string());
+        assert_eq!("test.tele:2:8: unmatched `)`", reader.read().unwrap_err().to_string());
+        assert_eq!(
+            "test.tele:3:4: unclosed `[`: expected `]`, found `)` at 3:8",
+            reader.read().unwrap_err().to_string()
+        );
+        assert_eq!(4, reader.read().unwrap().unwrap().span().unwrap().start.line);
+
+        let mut reader = read_from("1 (a) 2x");
+        assert_eq!(Expr::from(1), reader.read().unwrap().unwrap());
+        assert_eq!("(a)", reader.read().unwrap().unwrap().to_string());
+        assert_eq!("test.tele:1:7: unexpected `2x`", reader.read().unwrap_err().to_string());
+        assert!(reader.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn bad_tokens() {
+        let errors: Vec<_> = read_from("(a 1x)\n(b \\bogus c)\n\"a \\q b\" c @ (d\n\"open")
+            .map(|form| form.unwrap_err().to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "test.tele:1:4: unexpected `1x`",
+                "test.tele:2:4: unknown character: \\bogus",
+                // A bad string is skipped as a whole, and reported with the rest of its line
+                "test.tele:3:5: unknown escape in string: \\q\n\
+                 test.tele:3:12: unexpected `@`\n\
+                 test.tele:3:14: unclosed `(`: expected `)`, found end of input",
+                "test.tele:4:1: incomplete form, expected more input",
+            ],
+            errors
+        );
+    }
+
+    // Unfinished strings and comments aren't lexed again for every line they span,
+    // and whole forms aren't copied again for every form read from them
+    #[test]
+    fn long_input() {
+        let text = format!("\"{}\" #|{}|# (a \"b\n\\\"c\n|#\")", "x\n".repeat(20000), "y\n".repeat(20000));
+        let mut reader = read_from(&text);
+        assert_eq!(40000, reader.read().unwrap().unwrap().str().unwrap().len());
+        assert_eq!("(a \"b\\n\\\"c\\n|#\")", reader.read().unwrap().unwrap().to_string());
+
+        let text = "1 ".repeat(20000);
+        let forms = read_from(&text).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(20000, forms.len());
+        assert!(forms.iter().all(|form| *form == Expr::from(1)));
+    }
+}
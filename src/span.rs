@@ -0,0 +1,75 @@
+use std::fmt;
+use std::sync::Arc;
+
+// A line and column in a source, both counted from 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Position { line, column }
+    }
+
+    // Step past a character, moving to the next line on a newline
+    pub fn advance(&mut self, c: char) {
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new(1, 1)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// A region of a source file, from `start` up to (but not including) `end`
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub file: Option<Arc<str>>,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(file: Option<Arc<str>>, start: Position, end: Position) -> Self {
+        Span { file, start, end }
+    }
+
+    // An empty span at a single position
+    pub fn point(file: Option<Arc<str>>, position: Position) -> Self {
+        Span::new(file, position, position)
+    }
+
+    // The span from the start of this one to the end of another
+    pub fn to(&self, other: &Span) -> Span {
+        Span::new(self.file.clone(), self.start, other.end)
+    }
+
+    // The empty span at the end of this one
+    pub fn end_point(&self) -> Span {
+        Span::point(self.file.clone(), self.end)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => write!(f, "{}:{}", file, self.start),
+            None => write!(f, "{}", self.start),
+        }
+    }
+}
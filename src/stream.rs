@@ -1,79 +1,144 @@
-use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::Arc;
 
-use combine::StreamOnce;
-use combine::primitives::{Error, Positioner, SourcePosition};
+use combine::{ParseError, StreamOnce};
+use combine::primitives::Error;
 
+use span::{Position, Span};
 use token::Token;
 
+// Whether parsing failed only because the input ended early, e.g. in a block comment
+// or an unclosed list, so that reading more input might succeed
+pub fn is_incomplete<I: StreamOnce>(err: &ParseError<I>) -> bool {
+    err.errors.contains(&Error::end_of_input())
+}
+
 #[derive(Clone, Debug)]
 pub struct StringStream {
-    line: Vec<char>,
-    position: usize,
+    line: Rc<Vec<char>>,
+    index: usize,
+    position: Span,
 }
 
 impl StringStream {
+    #[cfg(test)]
     pub fn new(line: &str) -> Self {
+        StringStream::at(line, None, 1)
+    }
+
+    // A stream over text which starts at `line` of `file`
+    pub fn at(text: &str, file: Option<Arc<str>>, line: usize) -> Self {
+        StringStream::resume(text, Span::point(file, Position::new(line, 1)))
+    }
+
+    // A stream over text which carries on from `position`, e.g. part way through a line
+    pub fn resume(text: &str, position: Span) -> Self {
         StringStream {
-            line: line.chars().collect(),
-            position: 0,
+            line: Rc::new(text.chars().collect()),
+            index: 0,
+            position,
         }
     }
 }
 
-impl<'a> StreamOnce for StringStream {
+impl StringStream {
+    // How many characters have been read so far
+    pub fn offset(&self) -> usize {
+        self.index
+    }
+
+    // The text which hasn't been read yet
+    pub fn remainder(&self) -> String {
+        self.line[self.index..].iter().collect()
+    }
+
+    // The text between two offsets
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.line[start..end].iter().collect()
+    }
+}
+
+impl StreamOnce for StringStream {
     type Item = char;
     type Range = char;
-    type Position = usize;
+    type Position = Span;
 
     fn uncons(&mut self) -> Result<char, Error<char, char>> {
-        let ch = self.line.get(self.position)
+        let ch = self.line.get(self.index)
             .cloned()
-            .ok_or_else(Error::end_of_input);
-        self.position += 1;
-        ch
+            .ok_or_else(Error::end_of_input)?;
+        self.index += 1;
+        self.position.start.advance(ch);
+        self.position.end = self.position.start;
+        Ok(ch)
     }
 
     fn position(&self) -> Self::Position {
-        self.position
+        self.position.clone()
     }
 }
 
-#[derive(Clone, Debug)]
+// Tokens shared between every copy of the stream, so that backtracking doesn't copy them
+#[derive(Clone, Debug, Default)]
 pub struct TokenStream {
-    line: VecDeque<Token>,
-    position: SourcePosition,
+    tokens: Rc<[(Token, Span)]>,
+    index: usize,
+    end: Span,
 }
 
 impl TokenStream {
-    pub fn new<T>(line: T) -> Self
-    where T: Iterator<Item = Token>
+    pub fn new<T>(tokens: T) -> Self
+    where T: Iterator<Item = (Token, Span)>
     {
-        TokenStream {
-            line: line.collect(),
-            position: SourcePosition {column: 0, line: 0},
-        }
+        TokenStream::shared(tokens.collect::<Vec<_>>().into())
+    }
+
+    // A stream over tokens which may also be read by other streams
+    pub fn shared(tokens: Rc<[(Token, Span)]>) -> Self {
+        let end = tokens.last()
+            .map(|(_, span)| span.end_point())
+            .unwrap_or_default();
+        TokenStream { tokens, index: 0, end }
+    }
+
+    // The same tokens, read from `offset`
+    pub fn seek(&self, offset: usize) -> Self {
+        TokenStream { index: offset, ..self.clone() }
     }
 
-    pub fn unwrap(self) -> Vec<Token> {
-        self.line.into()
+    // How many tokens have been read so far
+    pub fn offset(&self) -> usize {
+        self.index
+    }
+
+    // The number of tokens left to parse
+    pub fn remaining(&self) -> usize {
+        self.tokens.len() - self.index
+    }
+
+    // The tokens left to parse
+    pub fn rest(&self) -> &[(Token, Span)] {
+        &self.tokens[self.index..]
     }
 }
 
 impl StreamOnce for TokenStream {
     type Item = Token;
     type Range = Token;
-    type Position = SourcePosition;
+    type Position = Span;
 
     fn uncons(&mut self) -> Result<Token, Error<Token, Token>> {
-        if let Some(token) = self.line.pop_front() {
-            token.update(&mut self.position);
-            Ok(token.clone())
-        } else {
-            Err(Error::end_of_input())
-        }
+        let token = self.tokens.get(self.index)
+            .map(|(token, _)| token.clone())
+            .ok_or_else(Error::end_of_input)?;
+        self.index += 1;
+        Ok(token)
     }
 
+    // The span of the next token, or the end of the last one
     fn position(&self) -> Self::Position {
-        self.position
+        self.tokens.get(self.index)
+            .map(|(_, span)| span.clone())
+            .unwrap_or_else(|| self.end.clone())
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,3 @@
-use combine::primitives::Positioner;
-use conv::ValueFrom;
 use std::fmt;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -8,6 +6,7 @@ pub enum Literal {
     Int(i64),
     Flt(f64),
     Str(String),
+    Char(char),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -16,31 +15,31 @@ pub enum Token {
     RParen,
     LBracket,
     RBracket,
+    LBrace,
+    RBrace,
     Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+    AnonFn,
+    Set,
+    Meta,
+    Discard,
     Literal(Literal),
     Symbol(String),
+    Keyword(String),
 }
 
-impl Positioner for Token {
-    type Position = <char as Positioner>::Position;
+// Characters which are written by name, e.g. \space
+pub const CHAR_NAMES: &[(&str, char)] = &[
+    ("space", ' '),
+    ("tab", '\t'),
+    ("newline", '\n'),
+    ("return", '\r'),
+];
 
-    fn start() -> Self::Position {
-        char::start()
-    }
-
-    fn update(&self, position: &mut Self::Position) {
-        match *self {
-            Token::LParen => position.column += 1,
-            Token::RParen => position.column += 1,
-            Token::LBracket => position.column += 1,
-            Token::RBracket => position.column += 1,
-            Token::Quote => position.column += 1,
-            Token::Literal(ref l) => {
-                position.column += i32::value_from(l.to_string().len()).unwrap()
-            }
-            Token::Symbol(ref s) => position.column += i32::value_from(s.len()).unwrap(),
-        }
-    }
+pub fn char_name(c: char) -> Option<&'static str> {
+    CHAR_NAMES.iter().find(|&&(_, named)| named == c).map(|&(name, _)| name)
 }
 
 impl fmt::Display for Literal {
@@ -50,6 +49,7 @@ impl fmt::Display for Literal {
             Literal::Int(ref i) => write!(f, "{}", i),
             Literal::Flt(ref x) => write!(f, "{}", x),
             Literal::Str(ref s) => write!(f, "{}", s),
+            Literal::Char(ref c) => write!(f, "{}", c),
         }
     }
 }
@@ -59,11 +59,49 @@ impl fmt::Display for Token {
         match *self {
             Token::Literal(ref lit) => write!(f, "{}", lit),
             Token::Symbol(ref s) => write!(f, "{}", s),
+            Token::Keyword(ref s) => write!(f, ":{}", s),
             _ => write!(f, "{:#?}", self),
         }
     }
 }
 
+impl Token {
+    // The token as it is written in source, e.g. ( rather than LParen
+    pub fn source(&self) -> String {
+        match *self {
+            Token::LParen => "(".into(),
+            Token::RParen => ")".into(),
+            Token::LBracket => "[".into(),
+            Token::RBracket => "]".into(),
+            Token::LBrace => "{".into(),
+            Token::RBrace => "}".into(),
+            Token::Quote => "'".into(),
+            Token::Quasiquote => "`".into(),
+            Token::Unquote => "~".into(),
+            Token::UnquoteSplicing => "~@".into(),
+            Token::AnonFn => "#(".into(),
+            Token::Set => "#{".into(),
+            Token::Meta => "^".into(),
+            Token::Discard => "#_".into(),
+            _ => self.to_string(),
+        }
+    }
+
+    // The token which closes this one, if it opens a form
+    pub fn closer(&self) -> Option<Token> {
+        match *self {
+            Token::LParen | Token::AnonFn => Some(Token::RParen),
+            Token::LBracket => Some(Token::RBracket),
+            Token::LBrace | Token::Set => Some(Token::RBrace),
+            _ => None,
+        }
+    }
+
+    pub fn is_closer(&self) -> bool {
+        matches!(*self, Token::RParen | Token::RBracket | Token::RBrace)
+    }
+}
+
 impl From<i32> for Literal {
     fn from(x: i32) -> Self {
         Literal::Int(x.into())
@@ -106,6 +144,12 @@ impl From<String> for Literal {
     }
 }
 
+impl From<char> for Literal {
+    fn from(x: char) -> Self {
+        Literal::Char(x)
+    }
+}
+
 impl<T> From<T> for Token
 where
     T: Into<Literal>,
@@ -141,5 +185,7 @@ mod test {
 
         assert_eq!("LParen", lparen.to_string());
         assert_eq!("1", literal.to_string());
+        assert_eq!("(", lparen.source());
+        assert_eq!("1", literal.source());
     }
 }
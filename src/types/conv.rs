@@ -8,10 +8,11 @@ where
 {
     fn from(x: T) -> Self {
         match x.into() {
-            Literal::Bool(y) => Expr::Bool(y),
-            Literal::Int(y) => Expr::Int(y),
-            Literal::Flt(y) => Expr::Flt(y),
-            Literal::Str(y) => Expr::Str(y),
+            Literal::Bool(y) => Expr::Bool(y, None),
+            Literal::Int(y) => Expr::Int(y, None),
+            Literal::Flt(y) => Expr::Flt(y, None),
+            Literal::Str(y) => Expr::Str(y, None),
+            Literal::Char(y) => Expr::Char(y, None),
         }
     }
 }
@@ -24,13 +25,13 @@ impl From<Symbol> for Expr {
 
 impl From<Vector> for List {
     fn from(x: Vector) -> Self {
-        List(x.0)
+        List { span: x.span, meta: x.meta, ..List::new(x.items) }
     }
 }
 
 impl From<List> for Vector {
     fn from(x: List) -> Self {
-        Vector(x.0)
+        Vector { items: x.items, span: x.span, meta: x.meta }
     }
 }
 
@@ -1,27 +1,35 @@
 #![allow(dead_code)]
 
 use super::*;
-use std::fmt;
+use error::*;
+use span::Span;
+use token::char_name;
+use std::fmt::{self, Write};
+use std::rc::Rc;
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub enum Expr {
     Nil,
-    Bool(bool),
-    Int(i64),
-    Flt(f64),
-    Str(String),
+    // Literals keep where they were read from, if they were read from source
+    Bool(bool, Option<Span>),
+    Int(i64, Option<Span>),
+    Flt(f64, Option<Span>),
+    Str(String, Option<Span>),
+    Char(char, Option<Span>),
     Sym(Symbol),
+    Keyword(Keyword),
     Func(Arc<Function>),
     Macro(Arc<Macro>),
     List(List),
     Vector(Vector),
     Map(Map),
+    Set(Set),
 }
 
 impl Expr {
     pub fn boolean(&self) -> Option<bool> {
-        if let Expr::Bool(x) = *self {
+        if let Expr::Bool(x, _) = *self {
             Some(x)
         } else {
             None
@@ -29,7 +37,7 @@ impl Expr {
     }
 
     pub fn int(&self) -> Option<i64> {
-        if let Expr::Int(x) = *self {
+        if let Expr::Int(x, _) = *self {
             Some(x)
         } else {
             None
@@ -37,7 +45,7 @@ impl Expr {
     }
 
     pub fn flt(&self) -> Option<f64> {
-        if let Expr::Flt(x) = *self {
+        if let Expr::Flt(x, _) = *self {
             Some(x)
         } else {
             None
@@ -45,12 +53,20 @@ impl Expr {
     }
 
     pub fn str(&self) -> Option<&str> {
-        if let Expr::Str(ref x) = *self {
+        if let Expr::Str(ref x, _) = *self {
             Some(x)
         } else {
             None
         }
     }
+    pub fn char(&self) -> Option<char> {
+        if let Expr::Char(x, _) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
     pub fn sym(&self) -> Option<&Symbol> {
         if let Expr::Sym(ref x) = *self {
             Some(x)
@@ -59,6 +75,14 @@ impl Expr {
         }
     }
 
+    pub fn keyword(&self) -> Option<&Keyword> {
+        if let Expr::Keyword(ref x) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
     pub fn list(&self) -> Option<&List> {
         if let Expr::List(ref x) = *self {
             Some(x)
@@ -75,6 +99,22 @@ impl Expr {
         }
     }
 
+    pub fn map(&self) -> Option<&Map> {
+        if let Expr::Map(ref x) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&self) -> Option<&Set> {
+        if let Expr::Set(ref x) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
     pub fn func(&self) -> Option<Arc<Function>> {
         if let Expr::Func(ref x) = *self {
             Some(x.clone())
@@ -83,10 +123,92 @@ impl Expr {
         }
     }
 
+    // Where this expression was read from, if it was read from source
+    pub fn span(&self) -> Option<&Span> {
+        match *self {
+            Expr::Bool(_, ref span) | Expr::Int(_, ref span) | Expr::Flt(_, ref span) => span.as_ref(),
+            Expr::Str(_, ref span) | Expr::Char(_, ref span) => span.as_ref(),
+            Expr::Sym(ref x) => x.span.as_ref(),
+            Expr::Keyword(ref x) => x.span.as_ref(),
+            Expr::List(ref x) => x.span.as_ref(),
+            Expr::Vector(ref x) => x.span.as_ref(),
+            Expr::Map(ref x) => x.span.as_ref(),
+            Expr::Set(ref x) => x.span.as_ref(),
+            _ => None,
+        }
+    }
+
+    // A copy of this expression read from `span`
+    pub fn with_span(self, span: Span) -> Expr {
+        match self {
+            Expr::Bool(x, _) => Expr::Bool(x, Some(span)),
+            Expr::Int(x, _) => Expr::Int(x, Some(span)),
+            Expr::Flt(x, _) => Expr::Flt(x, Some(span)),
+            Expr::Str(x, _) => Expr::Str(x, Some(span)),
+            Expr::Char(x, _) => Expr::Char(x, Some(span)),
+            Expr::Sym(x) => Expr::Sym(x.with_span(span)),
+            Expr::Keyword(x) => Expr::Keyword(x.with_span(span)),
+            Expr::List(x) => Expr::List(x.with_span(span)),
+            Expr::Vector(x) => Expr::Vector(x.with_span(span)),
+            Expr::Map(x) => Expr::Map(x.with_span(span)),
+            Expr::Set(x) => Expr::Set(x.with_span(span)),
+            _ => self,
+        }
+    }
+
+    // Metadata attached with ^{...} or with-meta, which never affects equality
+    pub fn meta(&self) -> Option<&Map> {
+        let meta = match *self {
+            Expr::Sym(ref x) => x.meta.as_ref(),
+            Expr::List(ref x) => x.meta.as_ref(),
+            Expr::Vector(ref x) => x.meta.as_ref(),
+            Expr::Map(ref x) => x.meta.as_ref(),
+            Expr::Set(ref x) => x.meta.as_ref(),
+            Expr::Func(ref x) => x.meta(),
+            _ => None,
+        };
+        meta.map(|meta| &**meta)
+    }
+
+    // A copy of this expression with its metadata replaced
+    pub fn with_meta(&self, meta: Option<Rc<Map>>) -> Result<Expr> {
+        let mut expr = self.clone();
+        match expr {
+            Expr::Sym(ref mut x) => x.meta = meta,
+            Expr::List(ref mut x) => x.meta = meta,
+            Expr::Vector(ref mut x) => x.meta = meta,
+            Expr::Map(ref mut x) => x.meta = meta,
+            Expr::Set(ref mut x) => x.meta = meta,
+            Expr::Func(ref x) => return Ok(Expr::from(x.with_meta(meta))),
+            _ => bail!("cannot attach metadata to: {}", self),
+        }
+        Ok(expr)
+    }
+
+    // A copy of this expression with more metadata added over what it already has
+    pub fn merge_meta(&self, meta: &Map) -> Result<Expr> {
+        let mut merged = self.meta().cloned().unwrap_or_default();
+        for (key, value) in meta.iter() {
+            merged.insert(key.clone(), value.clone());
+        }
+        merged.span = None;
+        self.with_meta(Some(Rc::new(merged)))
+    }
+
+    // How str and print show a value: strings and chars as their raw text, and
+    // anything else the way it would be read back
+    pub fn to_raw_string(&self) -> String {
+        match *self {
+            Expr::Str(ref string, _) => string.clone(),
+            Expr::Char(c, _) => c.to_string(),
+            _ => self.to_string(),
+        }
+    }
+
     pub fn truthiness(&self) -> bool {
         match *self {
             Expr::Nil => false,
-            Expr::Bool(b) => b,
+            Expr::Bool(b, _) => b,
             _ => true,
         }
     }
@@ -101,7 +223,7 @@ impl Expr {
 
     pub fn is_num(&self) -> bool {
         match *self {
-            Expr::Flt(_) | Expr::Int(_) => true,
+            Expr::Flt(..) | Expr::Int(..) => true,
             _ => false,
         }
     }
@@ -112,7 +234,7 @@ impl Expr {
         E: Into<Expr>
     {
         match self {
-            Expr::Int(int) => f(int).into(),
+            Expr::Int(int, _) => f(int).into(),
             _ => self,
         }
     }
@@ -123,28 +245,64 @@ impl Expr {
         E: Into<Expr>
     {
         match self {
-            Expr::Flt(flt) => f(flt).into(),
+            Expr::Flt(flt, _) => f(flt).into(),
             _ => self,
         }
     }
 }
 
+// Data prints the way it is written, so that reading it back gives an equal value
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Expr::Nil => write!(f, "()"),
-            Expr::Bool(boolean) => write!(f, "#{}", if boolean { "t" } else { "f" }),
-            Expr::Int(int) => write!(f, "{}", int),
-            Expr::Flt(flt) => write!(f, "{}", flt),
-            Expr::Str(ref string) => write!(f, "\"{}\"", string),
-            Expr::Sym(ref sym) => write!(f, "{}", sym.0),
+            Expr::Nil => write!(f, "nil"),
+            Expr::Bool(boolean, _) => write!(f, "#{}", if boolean { "t" } else { "f" }),
+            Expr::Int(int, _) => write!(f, "{}", int),
+            Expr::Flt(flt, _) => write_flt(f, flt),
+            Expr::Str(ref string, _) => write_str(f, string),
+            Expr::Char(c, _) => match char_name(c) {
+                Some(name) => write!(f, "\\{}", name),
+                None if c.is_control() || c.is_whitespace() => write!(f, "\\u{:04x}", c as u32),
+                None => write!(f, "\\{}", c),
+            },
+            Expr::Sym(ref sym) => write!(f, "{}", sym),
+            Expr::Keyword(ref keyword) => write!(f, "{}", keyword),
             Expr::Func(ref func) => write!(f, "{}", func),
             Expr::Macro(ref mac) => write!(f, "{}", mac),
             Expr::List(ref list) => write!(f, "{}", list),
             Expr::Vector(ref vec) => write!(f, "{}", vec),
             Expr::Map(ref map) => write!(f, "{}", map),
+            Expr::Set(ref set) => write!(f, "{}", set),
+        }
+    }
+}
+
+// Always with a . or an exponent, so that e.g. 1.0 doesn't read back as an int
+fn write_flt(f: &mut fmt::Formatter, flt: f64) -> fmt::Result {
+    if flt.is_nan() {
+        write!(f, "+nan.0")
+    } else if flt.is_infinite() {
+        write!(f, "{}inf.0", if flt > 0.0 { "+" } else { "-" })
+    } else {
+        write!(f, "{:?}", flt)
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\0' => f.write_str("\\0")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => f.write_char(c)?,
         }
     }
+    f.write_char('"')
 }
 
 impl PartialEq for Expr {
@@ -152,16 +310,19 @@ impl PartialEq for Expr {
         use self::Expr::*;
         match (self, other) {
             (&Nil, &Nil) => true,
-            (&Bool(ref a), &Bool(ref b)) => a == b,
-            (&Int(ref a), &Int(ref b)) => a == b,
-            (&Flt(ref a), &Flt(ref b)) => a == b,
-            (&Str(ref a), &Str(ref b)) => a == b,
+            (&Bool(ref a, _), &Bool(ref b, _)) => a == b,
+            (&Int(ref a, _), &Int(ref b, _)) => a == b,
+            (&Flt(ref a, _), &Flt(ref b, _)) => a == b,
+            (&Str(ref a, _), &Str(ref b, _)) => a == b,
+            (&Char(ref a, _), &Char(ref b, _)) => a == b,
             (&Sym(ref a), &Sym(ref b)) => a == b,
+            (&Keyword(ref a), &Keyword(ref b)) => a == b,
             (&Func(_), &Func(_)) => false,
             (&Macro(_), &Macro(_)) => false,
             (&List(ref a), &List(ref b)) => a == b,
             (&Vector(ref a), &Vector(ref b)) => a == b,
             (&Map(ref a), &Map(ref b)) => a == b,
+            (&Set(ref a), &Set(ref b)) => a == b,
             _ => false,
         }
     }
@@ -170,8 +331,11 @@ impl PartialEq for Expr {
 #[cfg(test)]
 mod test {
     use super::*;
+    use quickcheck::{Arbitrary, Gen};
     use env::Env;
+    use eval::Tail;
     use ops;
+    use util::testing::*;
 
     #[test]
     fn call_fn() {
@@ -181,10 +345,187 @@ mod test {
         );
 
         let nums: Vec<Expr> = vec![1i64, 2i64].into_iter().map(Expr::from).collect();
-        let result = add.apply(nums.as_slice(), env.clone());
+        let result = add.apply(nums.as_slice(), env.clone()).and_then(Tail::run);
         assert_eq!(Expr::from(3), result.unwrap());
     }
 
+    #[test]
+    fn error_location() {
+        assert_eq!(vec!["test.tele:2:4: undefined symbol: y"], eval_errors("(+ 1\n   y)", &ops::env()));
+    }
+
+    #[test]
+    fn keyword_lookup() {
+        let env = ops::env();
+        let mut map = Map::new();
+        map.insert(Key::Keyword(Keyword::new("name")), Expr::from("telescope"));
+        env.define("m", Expr::Map(map));
+
+        let values = eval_all(":name (:name m) (:other m) (:other m 1) (:name ())", &env);
+        assert_eq!(
+            vec![
+                Expr::Keyword(Keyword::new("name")),
+                Expr::from("telescope"),
+                Expr::Nil,
+                Expr::from(1),
+                Expr::Nil,
+            ],
+            values
+        );
+    }
+
+    #[test]
+    fn eval_vector() {
+        assert_eq!(
+            vec![Expr::Vector(Vector::new(vec![Expr::from(1), Expr::from(3)]))],
+            eval_all("[1 (+ 1 2)]", &ops::env())
+        );
+    }
+
+    #[test]
+    fn eval_map() {
+        let map = &eval_all("{:a (+ 1 2) \"b\" [:c] (- 3) #t}", &ops::env())[0];
+        assert_eq!("{\"b\" [:c] -3 #t :a 3}", map.to_string());
+        assert_eq!(Some(&Expr::from(true)), map.map().unwrap().get(&Key::Int(-3)));
+    }
+
+    #[test]
+    fn eval_map_order() {
+        let env = ops::env();
+        let printed = eval_printed("{1.5 :x -0.0 :y} {:a (def x 1) :b (def x 2) :c (def x 3)} x", &env);
+        assert_eq!("{0.0 :y 1.5 :x}", printed[0]);
+        assert_eq!("3", printed[2]);
+        let errors = eval_errors("{(+ 1 1) :a 2 :b}", &env);
+        assert_eq!(vec!["test.tele:1:1: duplicate key in map literal: 2"], errors);
+    }
+
+    #[test]
+    fn namespaced_symbols() {
+        let env = ops::env();
+        let values = eval_all("(def str/join 1) (def join 2) str/join join (/ 6 3) 'a/if", &env);
+        assert_eq!(vec![Expr::from(1), Expr::from(2), Expr::from(2)], values[2..5].to_vec());
+        assert_eq!(Expr::from(Symbol::qualified("a", "if")), values[5]);
+    }
+
+    #[test]
+    fn readable_printing() {
+        let values = eval_all(r#"(pr-str "say \"hi\"\n" \a \space 1.0 [-0.5 1e100] {"k" #t})
+            (str "say \"hi\"" \a 1.0 [\b "c"])
+            (pr-str (/ 1.0 0) (- (/ 1.0 0)) (/ 0.0 0))"#, &ops::env());
+        assert_eq!(
+            Expr::from(r#""say \"hi\"\n" \a \space 1.0 [-0.5 1e100] {"k" #t}"#),
+            values[0]
+        );
+        assert_eq!(Expr::from(r#"say "hi"a1.0[\b "c"]"#), values[1]);
+        assert_eq!(Expr::from("+inf.0 -inf.0 +nan.0"), values[2]);
+
+        let printed = Expr::Vector(Vector::new(vec![Expr::from("\u{7}\t"), Expr::from('\u{a0}')])).to_string();
+        assert_eq!(r#"["\u{7}\t" \u00a0]"#, printed);
+        assert!(read("+nan.0")[0].flt().unwrap().is_nan());
+    }
+
+    // A tree of data to build an Expr from, since Expr itself isn't Send
+    #[derive(Clone, Debug)]
+    enum Datum {
+        Nil,
+        Bool(bool),
+        Int(i64),
+        Flt(f64),
+        Str(String),
+        Char(char),
+        Sym(Option<String>, String),
+        Keyword(String),
+        List(Vec<Datum>),
+        Vector(Vec<Datum>),
+        Map(Vec<(Datum, Datum)>),
+        Set(Vec<Datum>),
+    }
+
+    impl Datum {
+        fn to_expr(&self) -> Expr {
+            let exprs = |items: &[Datum]| items.iter().map(Datum::to_expr).collect::<Vec<_>>();
+            let key = |item: &Datum| Key::try_from(&item.to_expr()).unwrap();
+            match *self {
+                Datum::Nil => Expr::Nil,
+                Datum::Bool(b) => Expr::from(b),
+                Datum::Int(i) => Expr::from(i),
+                Datum::Flt(x) => Expr::from(x),
+                Datum::Str(ref s) => Expr::from(s.clone()),
+                Datum::Char(c) => Expr::from(c),
+                Datum::Sym(Some(ref ns), ref name) => Expr::from(Symbol::qualified(ns, name)),
+                Datum::Sym(None, ref name) => Expr::from(Symbol::new(name.clone())),
+                Datum::Keyword(ref name) => Expr::Keyword(Keyword::new(name)),
+                Datum::List(ref items) => Expr::List(List::new(exprs(items))),
+                Datum::Vector(ref items) => Expr::Vector(Vector::new(exprs(items))),
+                Datum::Map(ref pairs) => {
+                    let mut map = Map::new();
+                    for (k, v) in pairs {
+                        map.insert(key(k), v.to_expr());
+                    }
+                    Expr::Map(map)
+                }
+                Datum::Set(ref items) => Expr::Set(items.iter().map(key).collect()),
+            }
+        }
+    }
+
+    fn datum_name<G: Gen>(g: &mut G) -> String {
+        const START: &[char] = &['a', 'x', 'λ', '_', '*', '!', '?', '<'];
+        const BODY: &[char] = &['a', 'x', '0', '9', '-', '?', '.'];
+        let len = g.gen_range(0, 6);
+        let mut name = vec![*g.choose(START).unwrap()];
+        name.extend((0..len).map(|_| *g.choose(BODY).unwrap()));
+        name.into_iter().collect()
+    }
+
+    fn datum_flt<G: Gen>(g: &mut G) -> f64 {
+        match g.gen_range(0, 5) {
+            0 => f64::INFINITY,
+            1 => f64::NEG_INFINITY,
+            2 => f64::arbitrary(g) * 10f64.powi(g.gen_range(-300, 300)),
+            _ => f64::arbitrary(g),
+        }
+    }
+
+    // Map keys and set items can be maps, but not sets
+    fn datum<G: Gen>(g: &mut G, depth: usize, key: bool) -> Datum {
+        let items = |g: &mut G, min| {
+            let len = g.gen_range(min, 4);
+            (0..len).map(|_| datum(g, depth - 1, key)).collect()
+        };
+        match g.gen_range(0, if depth == 0 { 8 } else { 12 }) {
+            0 => Datum::Nil,
+            1 => Datum::Bool(g.gen()),
+            2 => Datum::Int(g.gen()),
+            3 => Datum::Flt(datum_flt(g)),
+            4 => Datum::Str(String::arbitrary(g)),
+            5 => Datum::Char(char::arbitrary(g)),
+            6 => Datum::Sym(if g.gen() { Some(datum_name(g)) } else { None }, datum_name(g)),
+            7 => Datum::Keyword(datum_name(g)),
+            8 => Datum::List(items(g, 0)),
+            9 => Datum::Vector(items(g, 0)),
+            10 => {
+                let len = g.gen_range(0, 4);
+                Datum::Map((0..len).map(|_| (datum(g, depth - 1, true), datum(g, depth - 1, key))).collect())
+            }
+            11 if !key => Datum::Set((0..g.gen_range(0, 4)).map(|_| datum(g, depth - 1, true)).collect()),
+            _ => Datum::Int(g.gen()),
+        }
+    }
+
+    impl Arbitrary for Datum {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            datum(g, 3, false)
+        }
+    }
+
+    quickcheck! {
+        fn print_read_round_trip(datum: Datum) -> bool {
+            let expr = datum.to_expr();
+            read(&expr.to_string()) == vec![expr]
+        }
+    }
+
     #[test]
     fn test_env() {
         let new_scope = Env::default();
@@ -3,26 +3,55 @@
 use itertools::Itertools;
 
 use super::expr::Expr;
-use super::symbol::Symbol;
+use super::{Map, Params};
 use env::Env;
 use error::*;
 use std::fmt;
+use std::rc::Rc;
 
+#[derive(Clone)]
 pub enum Function {
     Builtin {
         name: String,
-        func: Lambda
+        func: Lambda,
+        meta: Option<Rc<Map>>,
     },
     User {
         name: Option<String>,
-        params: Vec<Symbol>,
-        body: Vec<Expr>,
+        clauses: Vec<Clause>,
         env: Env,
+        meta: Option<Rc<Map>>,
     },
 }
 
 pub type Lambda = fn(&[Expr], Env) -> Result<Expr>;
 
+// One arity of a user function: its parameters, and the body they're bound for
+#[derive(Clone, Debug)]
+pub struct Clause {
+    pub params: Params,
+    pub body: Vec<Expr>,
+}
+
+impl Clause {
+    pub fn arity(&self) -> usize {
+        self.params.min_args()
+    }
+
+    // The most arguments the clause takes, or none if it takes the rest
+    pub fn max_arity(&self) -> Option<usize> {
+        self.params.max_args()
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        self.max_arity().is_none()
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.arity() && self.max_arity().is_none_or(|max| count <= max)
+    }
+}
+
 impl Function {
     pub fn builtin<S>(name: S, func: Lambda) -> Self
     where
@@ -30,8 +59,52 @@ impl Function {
     {
         Function::Builtin {
             name: name.into(),
-            func: func,
+            func,
+            meta: None,
+        }
+    }
+}
+
+impl Function {
+    // The clause to call with `count` arguments, preferring one with room for
+    // exactly that many to one which takes the rest
+    pub fn clause(clauses: &[Clause], count: usize) -> Option<&Clause> {
+        clauses.iter().find(|clause| !clause.is_variadic() && clause.accepts(count))
+            .or_else(|| clauses.iter().find(|clause| clause.accepts(count)))
+    }
+
+    // How many arguments the clauses take between them, e.g. "0, 1-2 or at least 3"
+    pub fn arities(clauses: &[Clause]) -> String {
+        let mut arities = clauses.iter()
+            .sorted_by(|a, b| (a.is_variadic(), a.arity()).cmp(&(b.is_variadic(), b.arity())))
+            .into_iter()
+            .map(|clause| match clause.max_arity() {
+                None => format!("at least {}", clause.arity()),
+                Some(max) if max == clause.arity() => max.to_string(),
+                Some(max) => format!("{}-{}", clause.arity(), max),
+            })
+            .collect::<Vec<_>>();
+        match arities.pop() {
+            Some(last) if !arities.is_empty() => format!("{} or {}", arities.join(", "), last),
+            Some(last) => last,
+            None => String::new(),
+        }
+    }
+
+    pub fn meta(&self) -> Option<&Rc<Map>> {
+        match *self {
+            Function::Builtin { ref meta, .. } => meta.as_ref(),
+            Function::User { ref meta, .. } => meta.as_ref(),
+        }
+    }
+
+    pub fn with_meta(&self, meta: Option<Rc<Map>>) -> Self {
+        let mut func = self.clone();
+        match func {
+            Function::Builtin { meta: ref mut old, .. } => *old = meta,
+            Function::User { meta: ref mut old, .. } => *old = meta,
         }
+        func
     }
 }
 
@@ -42,11 +115,10 @@ impl fmt::Debug for Function {
                 => f.debug_struct("Function::Builtin")
                     .field("name", &name)
                     .finish(),
-            Function::User { ref name, ref params, ref body, env: _ }
+            Function::User { ref name, ref clauses, .. }
                 => f.debug_struct("Function::User")
                     .field("name", &name)
-                    .field("params", &params)
-                    .field("body", &body)
+                    .field("clauses", &clauses)
                     .finish(),
         }
     }
@@ -55,13 +127,70 @@ impl fmt::Debug for Function {
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Function::Builtin { ref name, func: _ } => write!(f, "#[{}]", name),
-            Function::User { name: _, ref params, ref body, env: _ } => {
+            Function::Builtin { ref name, .. } => write!(f, "#[{}]", name),
+            Function::User { ref clauses, .. } if clauses.len() == 1 => {
                 write!( f, "(fn [{}] {})",
-                    params.iter().join(" "),
-                    body.iter().join("\n")
+                    clauses[0].params,
+                    clauses[0].body.iter().join("\n")
+                )
+            }
+            // (fn ([] 0) ([x] x))
+            Function::User { ref clauses, .. } => {
+                write!( f, "(fn {})",
+                    clauses.iter()
+                        .map(|clause| format!("([{}] {})", clause.params, clause.body.iter().join("\n")))
+                        .join(" ")
                 )
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ops;
+    use util::testing::*;
+
+    #[test]
+    fn multi_arity() {
+        let env = ops::env();
+        let printed = eval_printed("(def greet (fn greet ([] (greet \"world\")) ([n] (str \"hello \" n))))
+            (greet) (greet \"you\")
+            (def f (fn ([] 0) ([a b & more] (list a b more)) ([a] :one)))
+            (f) (f 1) (f 1 2) (f 1 2 3)
+            f
+            (def count-down (fn ([n] (count-down n [])) ([n acc] (if (= n 0) acc (count-down (- n 1) (conj acc n))))))
+            (count-down 3)
+            (let [g (fn greet ([] (greet \"world\")) ([n] (str \"hi \" n)))] (g))
+            (let [fact (fn f [n] (if (= n 0) 1 (* n (f (- n 1)))))] (fact 5))
+            (def again 1)
+            (let [g (fn again [n] (if (= n 0) :done (again (- n 1))))] (g 2))", &env);
+        assert_eq!(vec!["\"hello world\"", "\"hello you\""], printed[1..3].to_vec());
+        assert_eq!(vec!["0", ":one", "(1 2 ())", "(1 2 (3))"], printed[4..8].to_vec());
+        assert_eq!("(fn ([] 0) ([a b & more] (list a b more)) ([a] :one))", printed[8]);
+        assert_eq!("[3 2 1]", printed[10]);
+        // The name is bound inside the fn itself, not only where it's defined
+        assert_eq!(vec!["\"hi world\"", "120"], printed[11..13].to_vec());
+        assert_eq!(":done", printed[14]);
+
+        let errors = eval_errors("(greet 1 2)
+            (fn ([a & b] 1) ([& c] 2))
+            (fn ([a] 1) ([b] 2))
+            (fn ([a b] 1) ([a & b] 2))
+            (fn f)
+            (fn ([a] 1) [b])
+            (fn ([a] 1) (b 2))", &env);
+        assert_eq!(
+            vec![
+                "test.tele:1:1: #[greet] expected 0 or 1 args",
+                "test.tele:2:13: #[fn] can't have more than one variadic clause",
+                "test.tele:3:13: #[fn] can't have two clauses with 1 params",
+                "test.tele:4:13: #[fn] can't have a clause with more params than the variadic one",
+                "test.tele:5:13: #[fn] expected parameter vector or clauses",
+                "test.tele:6:13: #[fn] expected list",
+                "test.tele:7:25: #[fn] expected vector",
+            ],
+            errors
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use span::Span;
+
+lazy_static! {
+    static ref KEYWORDS: Mutex<HashSet<Arc<str>>> = Mutex::new(HashSet::new());
+}
+
+// An interned name, written :name. Keywords with the same name share storage,
+// so comparing them is a pointer comparison.
+#[derive(Clone, Debug)]
+pub struct Keyword {
+    name: Arc<str>,
+    pub span: Option<Span>,
+}
+
+impl Keyword {
+    pub fn new(name: &str) -> Self {
+        let mut keywords = KEYWORDS.lock().unwrap();
+        if let Some(interned) = keywords.get(name) {
+            return Keyword { name: interned.clone(), span: None };
+        }
+
+        let interned: Arc<str> = Arc::from(name);
+        keywords.insert(interned.clone());
+        Keyword { name: interned, span: None }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Keyword { span: Some(span), ..self }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for Keyword {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.name, &other.name)
+    }
+}
+
+impl Eq for Keyword {}
+
+impl Hash for Keyword {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state)
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ":{}", self.name())
+    }
+}
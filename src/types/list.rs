@@ -1,18 +1,38 @@
-use super::Expr;
+use super::{Expr, Map};
 use itertools::Itertools;
+use span::Span;
+use std::cell::Cell;
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Clone, Debug)]
-pub struct List(pub Vec<Expr>);
+pub struct List {
+    pub items: Vec<Expr>,
+    pub span: Option<Span>,
+    pub meta: Option<Rc<Map>>,
+    // Set once the recurs in a fn or loop form have been checked, so evaluating
+    // it again doesn't walk the whole form again
+    pub(crate) recur_checked: Cell<bool>,
+}
+
+impl List {
+    pub fn new(items: Vec<Expr>) -> Self {
+        List { items, span: None, meta: None, recur_checked: Cell::new(false) }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        List { span: Some(span), ..self }
+    }
+}
 
 impl fmt::Display for List {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({})", self.0.iter().join(" "))
+        write!(f, "({})", self.items.iter().join(" "))
     }
 }
 
 impl PartialEq for List {
     fn eq(&self, other: &Self) -> bool {
-    	self.0 == other.0
+    	self.items == other.items
     }
 }
@@ -1,17 +1,17 @@
 use std::fmt;
 use itertools::Itertools;
 
-use super::{Expr, Symbol};
+use super::{Expr, Params};
 
 #[derive(Debug)]
 pub struct Macro {
     pub name: Option<String>,
-    pub params: Vec<Symbol>,
+    pub params: Params,
     pub body: Vec<Expr>,
 }
 
 impl Macro {
-    pub fn new<S>(name: S, params: Vec<Symbol>, body: Vec<Expr>) -> Self
+    pub fn new<S>(name: S, params: Params, body: Vec<Expr>) -> Self
     where
         S: Into<Option<String>>
     {
@@ -22,7 +22,7 @@ impl Macro {
 impl fmt::Display for Macro {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "(macro [{}] {})",
-            self.params.iter().join(" "),
+            self.params,
             self.body.iter().join("\n")
         )
     }
@@ -1,62 +1,176 @@
 #![allow(dead_code)]
 
+use std::borrow::Cow;
 use std::fmt;
 use std::collections::HashMap;
+use std::collections::hash_map;
+use std::rc::Rc;
 use itertools::Itertools;
-use super::Expr;
+use super::{Expr, Keyword, List, Symbol, Vector};
 use error::*;
+use span::Span;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Key {
     Nil,
     Bool(bool),
     Int(i64),
+    // A float by its bits, with 0.0 and -0.0 the same key
+    Flt(u64),
     Str(String),
+    Char(char),
+    Keyword(Keyword),
+    Sym(Symbol),
+    List(Vec<Key>),
+    Vector(Vec<Key>),
+    // Sorted by how they print, so that equal maps are the same key wherever they were read
+    Map(Vec<(Key, Key)>),
 }
 
 impl Key {
     pub fn try_from(expr: &Expr) -> Result<Self> {
         match *expr {
             Expr::Nil => Ok(Key::Nil),
-            Expr::Bool(b) => Ok(Key::Bool(b)),
-            Expr::Int(i) => Ok(Key::Int(i)),
-            Expr::Str(ref s) => Ok(Key::Str(s.clone())),
+            Expr::Bool(b, _) => Ok(Key::Bool(b)),
+            Expr::Int(i, _) => Ok(Key::Int(i)),
+            Expr::Flt(x, _) => Ok(Key::Flt(if x == 0.0 { 0f64.to_bits() } else { x.to_bits() })),
+            Expr::Str(ref s, _) => Ok(Key::Str(s.clone())),
+            Expr::Char(c, _) => Ok(Key::Char(c)),
+            Expr::Keyword(ref k) => Ok(Key::Keyword(k.clone())),
+            Expr::Sym(ref s) => Ok(Key::Sym(s.clone())),
+            Expr::List(ref l) => Key::try_from_all(&l.items).map(Key::List),
+            Expr::Vector(ref v) => Key::try_from_all(&v.items).map(Key::Vector),
+            Expr::Map(ref m) => {
+                let pairs = m.iter().map(|(key, value)| Ok((key.clone(), Key::try_from(value)?)));
+                let mut pairs = pairs.collect::<Result<Vec<_>>>()?;
+                pairs.sort_by_cached_key(|(key, value)| (key.to_string(), value.to_string()));
+                Ok(Key::Map(pairs))
+            }
             _ => Err(format!("cannot use as key: {}", expr).into()),
         }
     }
+
+    fn try_from_all(exprs: &[Expr]) -> Result<Vec<Self>> {
+        exprs.iter().map(Key::try_from).collect()
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        match *self {
+            Key::Nil => Expr::Nil,
+            Key::Bool(b) => Expr::Bool(b, None),
+            Key::Int(i) => Expr::Int(i, None),
+            Key::Flt(bits) => Expr::Flt(f64::from_bits(bits), None),
+            Key::Str(ref s) => Expr::Str(s.clone(), None),
+            Key::Char(c) => Expr::Char(c, None),
+            Key::Keyword(ref k) => Expr::Keyword(k.clone()),
+            Key::Sym(ref s) => Expr::Sym(s.clone()),
+            Key::List(ref l) => Expr::List(List::new(l.iter().map(Key::to_expr).collect())),
+            Key::Vector(ref v) => Expr::Vector(Vector::new(v.iter().map(Key::to_expr).collect())),
+            Key::Map(ref m) => {
+                let mut map = Map::new();
+                for (key, value) in m {
+                    map.insert(key.clone(), value.to_expr());
+                }
+                Expr::Map(map)
+            }
+        }
+    }
 }
 
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Key::Nil => write!(f, "()"),
-            Key::Bool(b) => write!(f, "{}", b),
-            Key::Int(i) => write!(f, "{}", i),
-            Key::Str(ref s) => write!(f, "{}", s),
-        }
+        write!(f, "{}", self.to_expr())
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Map(HashMap<Key, Expr>);
+#[derive(Clone, Debug)]
+pub struct Map {
+    entries: HashMap<Key, Expr>,
+    // The keys and values of a map literal as they were written, unevaluated and
+    // in order, until the map is changed
+    form: Option<Rc<Vec<(Expr, Expr)>>>,
+    pub span: Option<Span>,
+    pub meta: Option<Rc<Map>>,
+}
 
 impl Map {
     pub fn new() -> Self {
-        Map(HashMap::new())
+        Map { entries: HashMap::new(), form: None, span: None, meta: None }
+    }
+
+    // A map literal with these keys and values, which must all be different
+    pub fn from_forms(pairs: Vec<(Expr, Expr)>) -> Result<Self> {
+        let mut map = Map::new();
+        for (key, value) in &pairs {
+            let key = Key::try_from(key)?;
+            if map.insert(key.clone(), value.clone()).is_some() {
+                bail!("duplicate key in map literal: {}", key);
+            }
+        }
+        map.form = Some(Rc::new(pairs));
+        Ok(map)
+    }
+
+    // The keys and values as code: as they were written if this is a map literal,
+    // or else in no particular order
+    pub fn forms(&self) -> Cow<'_, [(Expr, Expr)]> {
+        match self.form {
+            Some(ref form) => Cow::Borrowed(form),
+            None => Cow::Owned(self.entries.iter().map(|(key, value)| (key.to_expr(), value.clone())).collect()),
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Map { span: Some(span), ..self }
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Expr> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: Key, value: Expr) -> Option<Expr> {
+        self.form = None;
+        self.entries.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Expr> {
+        self.form = None;
+        self.entries.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, Key, Expr> {
+        self.entries.iter()
     }
 }
 
 impl Default for Map {
     fn default() -> Self {
-        Map(HashMap::new())
+        Map::new()
     }
 }
 
 impl fmt::Display for Map {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let pairs = self.0.iter()
-            .map(|(key, val)| format!("{}: {}", key, val))
-            .join(", ");
+        // Sort entries so that equal maps always print the same way
+        let pairs = self.entries.iter()
+            .map(|(key, val)| format!("{} {}", key, val))
+            .sorted()
+            .into_iter()
+            .join(" ");
         write!(f, "{{{}}}", pairs)
     }
 }
+
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries
+    }
+}
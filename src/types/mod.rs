@@ -1,16 +1,22 @@
 mod expr;
 mod symbol;
+mod keyword;
 mod function;
 mod mac;
+mod params;
 mod list;
 mod vector;
 mod map;
+mod set;
 mod conv;
 
 pub use self::expr::Expr;
-pub use self::function::{Function, Lambda};
+pub use self::function::{Clause, Function, Lambda};
 pub use self::mac::Macro;
+pub use self::params::{Params, Pattern};
 pub use self::list::List;
 pub use self::symbol::Symbol;
+pub use self::keyword::Keyword;
 pub use self::vector::Vector;
-pub use self::map::Map;
+pub use self::map::{Key, Map};
+pub use self::set::Set;
@@ -0,0 +1,478 @@
+use std::fmt;
+use itertools::Itertools;
+
+use super::{Expr, Key, Keyword, List, Map, Symbol, Vector};
+use env::Env;
+use error::*;
+use util::*;
+
+// The parameters of a function or macro: some which take one argument each,
+// some after `:opt` which take one if it's there, and perhaps either one after `&`
+// which takes any more as a list or some after `:key` which are given by keyword
+#[derive(Clone, Debug)]
+pub struct Params {
+    pub required: Vec<Pattern>,
+    pub optional: Vec<(Pattern, Expr)>,
+    pub rest: Option<Box<Pattern>>,
+    pub keys: Vec<(Symbol, Expr)>,
+}
+
+impl Params {
+    // Parse a parameter vector such as [a [b c] :opt [d 0] & more] or
+    // [a :key [port 80]] for the form named `form`
+    pub fn parse(form: &str, params: &Vector) -> Result<Self> {
+        let is_marker = |expr: &Expr| is_keyword(expr, "opt") || is_keyword(expr, "key");
+        let mut items = &params.items[..];
+        let split = items.iter().position(is_marker).unwrap_or(items.len());
+        let (required, mut rest, whole) = parse_seq(form, &items[..split])?;
+        ensure!(whole.is_none(), "#[{}] unexpected :as in parameters", form);
+        items = &items[split..];
+
+        let mut optional = Vec::new();
+        if let Some(marker) = items.first().filter(|marker| is_keyword(marker, "opt")) {
+            ensure!(rest.is_none(), "#[{}] expected :opt before &", form);
+            for (name, default) in defaults(form, marker, items.get(1))? {
+                optional.push((Pattern::parse(form, name)?, default.clone()));
+            }
+            // & rest may come after the optional parameters
+            let split = items.iter().skip(2).position(is_marker).map_or(items.len(), |i| i + 2);
+            let (more, after, _) = parse_seq(form, items.get(2..split).unwrap_or(&[]))?;
+            ensure!(more.is_empty(), "#[{}] expected required parameters before :opt", form);
+            rest = after;
+            items = items.get(split..).unwrap_or(&[]);
+        }
+
+        let mut keys = Vec::new();
+        if let Some(marker) = items.first().filter(|marker| is_keyword(marker, "key")) {
+            ensure!(rest.is_none(), "#[{}] can't have both & and :key parameters", form);
+            for (name, default) in defaults(form, marker, items.get(1))? {
+                keys.push((ensure_sym(form, name)?.clone(), default.clone()));
+            }
+            items = items.get(2..).unwrap_or(&[]);
+        }
+        if let Some(item) = items.first() {
+            bail!("#[{}] unexpected {} in parameters", form, item);
+        }
+
+        Ok(Params { required, optional, rest: rest.map(Box::new), keys })
+    }
+
+    // How many arguments the parameters take, with no most if they take the rest
+    // or keyword arguments
+    pub fn min_args(&self) -> usize {
+        self.required.len()
+    }
+
+    pub fn max_args(&self) -> Option<usize> {
+        if self.rest.is_some() || !self.keys.is_empty() {
+            None
+        } else {
+            Some(self.required.len() + self.optional.len())
+        }
+    }
+
+    // Bind arguments to the parameters of the function or macro `name` in `env`.
+    // Defaults are evaluated in `env` when they're needed, after the parameters
+    // before them are bound.
+    pub fn bind(&self, name: &str, args: &[Expr], env: &Env) -> Result<()> {
+        let count = self.required.len();
+        match self.max_args() {
+            None => ensure_min_args(name, args, count)?,
+            Some(max) if max == count => ensure_args(name, args, count)?,
+            Some(max) => ensure_range_args(name, args, count, max)?,
+        }
+
+        for (param, arg) in self.required.iter().zip(args) {
+            param.bind(name, arg.clone(), env)?;
+        }
+        // Optional arguments stop at the first keyword argument, so they can be left out before one
+        let is_key = |arg: &Expr| {
+            arg.keyword().is_some_and(|keyword| self.keys.iter().any(|(key, _)| key.name == keyword.name()))
+        };
+        let given = args.iter().skip(count).take(self.optional.len()).take_while(|arg| !is_key(arg)).count();
+        for (i, (param, default)) in self.optional.iter().enumerate() {
+            let value = match args.get(count + i).filter(|_| i < given) {
+                Some(arg) => arg.clone(),
+                None => default.eval(env.clone())?,
+            };
+            param.bind(name, value, env)?;
+        }
+
+        let more = args.get(count + given..).unwrap_or(&[]);
+        if let Some(ref rest) = self.rest {
+            rest.bind(name, Expr::List(List::new(more.to_vec())), env)?;
+        }
+        if !self.keys.is_empty() {
+            self.bind_keys(name, more, env)?;
+        }
+        Ok(())
+    }
+
+    // Bind keyword arguments such as :port 80, which must each name a parameter once
+    fn bind_keys(&self, name: &str, args: &[Expr], env: &Env) -> Result<()> {
+        let mut values = vec![None; self.keys.len()];
+        for pair in args.chunks(2) {
+            let keyword = pair[0].keyword()
+                .ok_or_else(|| format!("#[{}] expected keyword argument, found {}", name, pair[0]))?;
+            let i = self.keys.iter().position(|(key, _)| key.name == keyword.name())
+                .ok_or_else(|| format!("#[{}] unknown keyword argument {}", name, pair[0]))?;
+            ensure!(values[i].is_none(), "#[{}] duplicate keyword argument {}", name, pair[0]);
+            let value = pair.get(1)
+                .ok_or_else(|| format!("#[{}] expected value for keyword argument {}", name, pair[0]))?;
+            values[i] = Some(value.clone());
+        }
+
+        for ((key, default), value) in self.keys.iter().zip(values) {
+            let value = match value {
+                Some(value) => value,
+                None => default.eval(env.clone())?,
+            };
+            env.define(&key.qualified_name(), value);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Params {
+    // a :opt [b 0] & more, or a :key [c 1]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = self.required.iter().map(Pattern::to_string).collect::<Vec<_>>();
+        if !self.optional.is_empty() {
+            let mut optional = self.optional.iter().map(|(name, default)| format!("{} {}", name, default));
+            parts.push(format!(":opt [{}]", optional.join(" ")));
+        }
+        if let Some(ref rest) = self.rest {
+            parts.push(format!("& {}", rest));
+        }
+        if !self.keys.is_empty() {
+            let mut keys = self.keys.iter().map(|(name, default)| format!("{} {}", name, default));
+            parts.push(format!(":key [{}]", keys.join(" ")));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+// Where a binding puts its value: under a name, or taken apart into the names in
+// a vector or map
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Name(Symbol),
+    // [a b & more :as whole]
+    Seq {
+        items: Vec<Pattern>,
+        rest: Option<Box<Pattern>>,
+        whole: Option<Symbol>,
+    },
+    // {a :a :keys [b c] :or {c 0} :as whole}, kept as written for printing
+    Map {
+        entries: Vec<(Pattern, Key)>,
+        defaults: Vec<(String, Expr)>,
+        whole: Option<Symbol>,
+        form: Map,
+    },
+}
+
+impl Pattern {
+    pub fn parse(form: &str, expr: &Expr) -> Result<Self> {
+        let pattern = match *expr {
+            Expr::Sym(ref name) => Ok(Pattern::Name(name.clone())),
+            Expr::Vector(ref vector) => parse_seq(form, &vector.items).map(|(items, rest, whole)| {
+                Pattern::Seq { items, rest: rest.map(Box::new), whole }
+            }),
+            Expr::Map(ref map) => parse_map(form, map),
+            _ => Err(format!("#[{}] expected symbol, vector or map to bind, found {}", form, expr).into()),
+        };
+        pattern.map_err(|err| err.at(expr.span()))
+    }
+
+    // Define the names in the pattern in `env`, taking `value` apart to fit it.
+    // Defaults for missing keys are evaluated in `env`, so they can use names bound before them.
+    pub fn bind(&self, form: &str, value: Expr, env: &Env) -> Result<()> {
+        match *self {
+            Pattern::Name(ref name) => {
+                // (let [^{:doc "..."} name init]) gives the value the name's metadata
+                let value = match name.meta {
+                    Some(ref meta) => value.merge_meta(meta).unwrap_or(value),
+                    None => value,
+                };
+                env.define(&name.qualified_name(), value);
+            }
+            Pattern::Seq { ref items, ref rest, ref whole } => {
+                let values = match value {
+                    Expr::List(ref list) => list.items.clone(),
+                    Expr::Vector(ref vector) => vector.items.clone(),
+                    Expr::Nil => Vec::new(),
+                    _ => bail!("#[{}] expected list or vector to destructure, found {}", form, value),
+                };
+
+                // Names past the end of the values are nil
+                for (i, item) in items.iter().enumerate() {
+                    item.bind(form, values.get(i).cloned().unwrap_or(Expr::Nil), env)?;
+                }
+                if let Some(ref rest) = *rest {
+                    let more = values.get(items.len()..).unwrap_or(&[]).to_vec();
+                    rest.bind(form, Expr::List(List::new(more)), env)?;
+                }
+                if let Some(ref whole) = *whole {
+                    env.define(&whole.qualified_name(), value);
+                }
+            }
+            Pattern::Map { ref entries, ref defaults, ref whole, .. } => {
+                let empty = Map::new();
+                let map = match value {
+                    Expr::Map(ref map) => map,
+                    Expr::Nil => &empty,
+                    _ => bail!("#[{}] expected map to destructure, found {}", form, value),
+                };
+
+                // Names with a default wait until everything in the map is bound, so that
+                // defaults can use any of those names as well as the ones before them
+                let mut missing = Vec::new();
+                for (pattern, key) in entries {
+                    match (map.get(key), pattern) {
+                        (Some(value), _) => pattern.bind(form, value.clone(), env)?,
+                        (None, Pattern::Name(name)) if defaults.iter().any(|(n, _)| *n == name.name) => {
+                            missing.push(pattern)
+                        }
+                        (None, _) => pattern.bind(form, Expr::Nil, env)?,
+                    }
+                }
+                for (name, default) in defaults {
+                    let pattern = missing.iter().find(|pattern| match pattern {
+                        Pattern::Name(sym) => sym.name == *name,
+                        _ => false,
+                    });
+                    if let Some(pattern) = pattern {
+                        pattern.bind(form, default.eval(env.clone())?, env)?;
+                    }
+                }
+                if let Some(ref whole) = *whole {
+                    env.define(&whole.qualified_name(), value.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Pattern::Name(ref name) => write!(f, "{}", name),
+            Pattern::Seq { ref items, ref rest, ref whole } => {
+                write!(f, "[")?;
+                write_seq(f, items, rest.as_deref(), whole.as_ref())?;
+                write!(f, "]")
+            }
+            Pattern::Map { ref form, .. } => write!(f, "{}", form),
+        }
+    }
+}
+
+// Patterns for [a b & more :as whole]
+fn parse_seq(form: &str, exprs: &[Expr]) -> Result<(Vec<Pattern>, Option<Pattern>, Option<Symbol>)> {
+    let mut items = Vec::new();
+    let mut rest = None;
+    let mut whole = None;
+    let mut exprs = exprs.iter();
+
+    while let Some(expr) = exprs.next() {
+        if is_keyword(expr, "as") {
+            whole = match (exprs.next(), exprs.next()) {
+                (Some(name), None) => Some(ensure_sym(form, name)?.clone()),
+                _ => bail!("#[{}] expected one name after :as", form),
+            };
+        } else if expr.sym().is_some_and(|sym| sym.ns.is_none() && sym.name == "&") {
+            ensure!(rest.is_none(), "#[{}] expected one parameter after &", form);
+            rest = match exprs.next() {
+                Some(expr) if !is_keyword(expr, "as") => Some(Pattern::parse(form, expr)?),
+                _ => bail!("#[{}] expected one parameter after &", form),
+            };
+        } else if rest.is_some() {
+            bail!("#[{}] expected one parameter after &", form);
+        } else {
+            items.push(Pattern::parse(form, expr)?);
+        }
+    }
+    Ok((items, rest, whole))
+}
+
+// Patterns for {a :a :keys [b c] :or {c 0} :as whole}
+fn parse_map(form: &str, map: &Map) -> Result<Pattern> {
+    let mut entries = Vec::new();
+    let mut defaults = Vec::new();
+    let mut whole = None;
+
+    // The written forms, so that nested patterns can be keys and defaults keep their order
+    for (key, value) in map.forms().iter() {
+        if is_keyword(key, "keys") {
+            let names = value.vector().ok_or_else(|| format!("#[{}] expected vector after :keys", form))?;
+            for name in &names.items {
+                let name = ensure_sym(form, name)?;
+                entries.push((Pattern::Name(name.clone()), Key::Keyword(Keyword::new(&name.name))));
+            }
+        } else if is_keyword(key, "or") {
+            let map = value.map().ok_or_else(|| format!("#[{}] expected map after :or", form))?;
+            for (name, default) in map.forms().iter() {
+                match *name {
+                    Expr::Sym(ref name) => defaults.push((name.name.clone(), default.clone())),
+                    _ => bail!("#[{}] expected symbol in :or, found {}", form, name),
+                }
+            }
+        } else if is_keyword(key, "as") {
+            whole = Some(ensure_sym(form, value)?.clone());
+        } else {
+            entries.push((Pattern::parse(form, key)?, Key::try_from(value)?));
+        }
+    }
+
+    Ok(Pattern::Map { entries, defaults, whole, form: map.clone() })
+}
+
+// The names and defaults in the vector after :opt or :key
+fn defaults<'a>(form: &str, marker: &Expr, vector: Option<&'a Expr>) -> Result<Vec<(&'a Expr, &'a Expr)>> {
+    let vector = vector.and_then(Expr::vector)
+        .ok_or_else(|| format!("#[{}] expected vector after {}", form, marker))?;
+    ensure!(
+        vector.items.len().is_multiple_of(2),
+        "#[{}] expected a name and default for each parameter after {}",
+        form,
+        marker
+    );
+    Ok(vector.items.chunks(2).map(|pair| (&pair[0], &pair[1])).collect())
+}
+
+fn is_keyword(expr: &Expr, name: &str) -> bool {
+    expr.keyword().is_some_and(|keyword| keyword.name() == name)
+}
+
+// a b & more :as whole
+fn write_seq(f: &mut fmt::Formatter, items: &[Pattern], rest: Option<&Pattern>, whole: Option<&Symbol>) -> fmt::Result {
+    let rest = rest.map(|rest| format!("& {}", rest));
+    let whole = whole.map(|whole| format!(":as {}", whole));
+    let mut parts = items.iter().map(Pattern::to_string).chain(rest).chain(whole);
+    write!(f, "{}", parts.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use ops;
+    use util::testing::*;
+
+    #[test]
+    fn variadic() {
+        let env = ops::env();
+        let printed = eval_printed("(def f (fn [a & more] [a more]))
+            (f 1) (f 1 2 3)
+            (def when (macro [test & body] `(if ~test (do ~@body) nil)))
+            (when #t 1 2) (when #f 1)
+            (def g #(list % %&)) (g 1 2 3)
+            (def h (fn [& all] all)) (h) (h 1 2)
+            f h", &env);
+        assert_eq!(vec!["[1 ()]", "[1 (2 3)]"], printed[1..3].to_vec());
+        assert_eq!(vec!["2", "nil"], printed[4..6].to_vec());
+        assert_eq!("(1 (2 3))", printed[7]);
+        assert_eq!(vec!["()", "(1 2)"], printed[9..11].to_vec());
+        assert_eq!(vec!["(fn [a & more] [a more])", "(fn [& all] all)"], printed[11..13].to_vec());
+
+        assert_eq!(
+            vec![
+                "test.tele:1:1: #[fn] expected at least 1 args",
+                "test.tele:1:5: #[macro] expected at least 1 args",
+                "test.tele:1:12: #[fn] expected one parameter after &",
+                "test.tele:1:25: #[fn] expected one parameter after &",
+                "test.tele:1:50: #[macro] expected symbol, vector or map to bind, found 1",
+            ],
+            eval_errors("(f) (when) (fn [a &] a) (fn [& a b] a) (macro [& 1] 1)", &env)
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        let env = ops::env();
+        let printed = eval_printed("(let [[a b & more :as all] [1 2 3 4]] [a b more all])
+            (let [{:keys [x y] :or {y (+ x 1)} :as m} {:x 1}] [x y m])
+            (let [{[p q] :point n :name} {:point [1 2]}] [p q n])
+            (def f (fn [[a b] {:keys [c]} & [d]] [a b c d]))
+            (f '(1 2) {:c 3} 4 5)
+            (f [1 2] nil)
+            (def m (macro [[op & args]] `(~op ~@args)))
+            (m (+ 1 2))
+            (loop [[x & xs] [1 2 3] acc 0] (if x (recur xs (+ acc x)) acc))
+            f
+            (let [{y :y z :z w :w :keys [x] :or {y (+ x 1) z (+ y 1) w (+ z 1)}} {:x 1 :z 5}] [x y z w])
+            (let [{{:keys [a]} :inner [b] :b} {:inner {:a 1} :b [2]}] [a b])
+            (let [[a b] [1]] b)", &env);
+        assert_eq!("[1 2 (3 4) [1 2 3 4]]", printed[0]);
+        assert_eq!("[1 2 {:x 1}]", printed[1]);
+        assert_eq!("[1 2 nil]", printed[2]);
+        assert_eq!("[1 2 3 4]", printed[4]);
+        assert_eq!("[1 2 nil nil]", printed[5]);
+        assert_eq!("3", printed[7]);
+        assert_eq!("6", printed[8]);
+        assert_eq!("(fn [[a b] {:keys [c]} & [d]] [a b c d])", printed[9]);
+        // Defaults come after everything in the map is bound, in the order they're written
+        assert_eq!("[1 2 5 6]", printed[10]);
+        assert_eq!("[1 2]", printed[11]);
+        assert_eq!("nil", printed[12]);
+
+        let errors = eval_errors("(let [{:keys [a]} [1]] a)
+            (let [[a] 1] a)
+            (let [a 1 b] a)
+            (let [1 2] 1)
+            (let [[a & b c] [1]] a)
+            (f 1 {})", &env);
+        assert_eq!(
+            vec![
+                "test.tele:1:1: #[let] expected map to destructure, found [1]",
+                "test.tele:2:13: #[let] expected list or vector to destructure, found 1",
+                "test.tele:3:18: #[let] expected an even number of forms in bindings",
+                "test.tele:4:19: #[let] expected symbol, vector or map to bind, found 1",
+                "test.tele:5:19: #[let] expected one parameter after &",
+                "test.tele:6:13: #[fn] expected list or vector to destructure, found 1",
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn optional_args() {
+        let env = ops::env();
+        let printed = eval_printed("(def connect (fn connect [host :opt [timeout 30] :key [port 80 retries (+ timeout 1)]]
+                [host timeout port retries]))
+            (connect \"h\") (connect \"h\" 5) (connect \"h\" 5 :retries 3 :port 8080)
+            connect
+            (connect \"e\" :port 2) (connect \"e\" :retries 3 :port 2)
+            (def f (fn ([] 0) ([a :opt [b a] & more] [a b more])))
+            (f) (f 1) (f 1 2 3)", &env);
+        assert_eq!(vec!["[\"h\" 30 80 31]", "[\"h\" 5 80 6]", "[\"h\" 5 8080 3]"], printed[1..4].to_vec());
+        assert_eq!(
+            "(fn [host :opt [timeout 30] :key [port 80 retries (+ timeout 1)]] [host timeout port retries])",
+            printed[4]
+        );
+        assert_eq!(vec!["[\"e\" 30 2 31]", "[\"e\" 30 2 3]"], printed[5..7].to_vec());
+        assert_eq!(vec!["0", "[1 1 ()]", "[1 2 (3)]"], printed[8..11].to_vec());
+
+        let errors = eval_errors("(connect)
+            (connect \"h\" 5 :timeout 1)
+            (connect \"h\" 5 :port 1 :port 2)
+            (connect \"h\" 5 :port)
+            (connect \"h\" 5 6)
+            (fn ([a :opt [b 1]] 1) ([a b] 2))
+            (fn [a & b :key [c 1]] 1)
+            (fn [a :opt [b]] 1)", &env);
+        assert_eq!(
+            vec![
+                "test.tele:1:1: #[connect] expected at least 1 args",
+                "test.tele:2:13: #[connect] unknown keyword argument :timeout",
+                "test.tele:3:13: #[connect] duplicate keyword argument :port",
+                "test.tele:4:13: #[connect] expected value for keyword argument :port",
+                "test.tele:5:13: #[connect] expected keyword argument, found 6",
+                "test.tele:6:13: #[fn] can't have two clauses with 2 params",
+                "test.tele:7:13: #[fn] can't have both & and :key parameters",
+                "test.tele:8:13: #[fn] expected a name and default for each parameter after :opt",
+            ],
+            errors
+        );
+    }
+}
@@ -0,0 +1,124 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::collections::HashSet;
+use std::collections::hash_set;
+use std::rc::Rc;
+use itertools::Itertools;
+use super::{Key, Map};
+use span::Span;
+
+#[derive(Clone, Debug)]
+pub struct Set {
+    items: HashSet<Key>,
+    pub span: Option<Span>,
+    pub meta: Option<Rc<Map>>,
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Set { items: HashSet::new(), span: None, meta: None }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Set { span: Some(span), ..self }
+    }
+
+    pub fn contains(&self, key: &Key) -> bool {
+        self.items.contains(key)
+    }
+
+    // Returns false if the key was already present
+    pub fn insert(&mut self, key: Key) -> bool {
+        self.items.insert(key)
+    }
+
+    pub fn remove(&mut self, key: &Key) -> bool {
+        self.items.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> hash_set::Iter<'_, Key> {
+        self.items.iter()
+    }
+
+    pub fn union(&self, other: &Set) -> Set {
+        self.items.union(&other.items).cloned().collect()
+    }
+
+    pub fn intersection(&self, other: &Set) -> Set {
+        self.items.intersection(&other.items).cloned().collect()
+    }
+
+    pub fn difference(&self, other: &Set) -> Set {
+        self.items.difference(&other.items).cloned().collect()
+    }
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::new()
+    }
+}
+
+impl ::std::iter::FromIterator<Key> for Set {
+    fn from_iter<T: IntoIterator<Item = Key>>(iter: T) -> Self {
+        Set { items: iter.into_iter().collect(), span: None, meta: None }
+    }
+}
+
+impl fmt::Display for Set {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Sort items so that equal sets always print the same way
+        let items = self.items.iter()
+            .map(Key::to_string)
+            .sorted()
+            .into_iter()
+            .join(" ");
+        write!(f, "#{{{}}}", items)
+    }
+}
+
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ops;
+    use util::testing::*;
+
+    #[test]
+    fn sets() {
+        let printed = eval_printed("#{1 (+ 1 1)}
+            (conj #{1} 2 1)
+            (disj #{1 2 3} 2 4)
+            (contains? #{:a} :a)
+            (contains? #{:a} :b)
+            (union #{1} #{2} #{3})
+            (intersection #{1 2 3} #{2 3 4} #{3})
+            (difference #{1 2 3} #{2})
+            (set [1 2 1])
+            (= #{1 [2]} (hash-set [2] 1))", &ops::env());
+        assert_eq!(
+            vec!["#{1 2}", "#{1 2}", "#{1 3}", "#t", "#f", "#{1 2 3}", "#{3}", "#{1 3}", "#{1 2}", "#t"],
+            printed
+        );
+
+        // Maps as items or keys are equal whatever order or place their entries were written in
+        let printed = eval_printed("
+            (= #{{[:a :b] 1 [:a :c] 2}} #{{[:a :c] 2 [:a :b] 1}})
+            (contains? #{{[:x] 1 [:y] 2}} {[:y] 2 [:x] 1})
+            (get {{[:a] 1 [:b] 2} :found} {[:b] 2 [:a] 1})", &ops::env());
+        assert_eq!(vec!["#t", "#t", ":found"], printed);
+    }
+}
@@ -1,10 +1,88 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Symbol(pub String);
+use span::Span;
+use super::Map;
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub ns: Option<String>,
+    pub name: String,
+    pub span: Option<Span>,
+    pub meta: Option<Rc<Map>>,
+}
+
+impl Symbol {
+    pub fn new<S>(name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Symbol { ns: None, name: name.into(), span: None, meta: None }
+    }
+
+    // ns/name
+    pub fn qualified<S, T>(ns: S, name: T) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        Symbol { ns: Some(ns.into()), ..Symbol::new(name) }
+    }
+
+    // Split symbol text as read by the lexer into its namespace and name
+    pub fn parse(text: &str) -> Self {
+        match text.find('/') {
+            Some(i) if text != "/" => Symbol::qualified(&text[..i], &text[i + 1..]),
+            _ => Symbol::new(text),
+        }
+    }
+
+    // The name including any namespace, as it is bound in an environment
+    pub fn qualified_name(&self) -> Cow<'_, str> {
+        match self.ns {
+            Some(ref ns) => Cow::Owned(format!("{}/{}", ns, self.name)),
+            None => Cow::Borrowed(&self.name),
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Symbol { span: Some(span), ..self }
+    }
+}
 
 impl fmt::Display for Symbol {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.qualified_name())
+    }
+}
+
+// Symbols are compared by namespace and name, wherever they were read from
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.ns == other.ns && self.name == other.name
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ns.hash(state);
+        self.name.hash(state)
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.ns, &self.name).cmp(&(&other.ns, &other.name))
     }
 }
@@ -1,18 +1,34 @@
-use super::expr::Expr;
+use super::{Expr, Map};
 use itertools::Itertools;
+use span::Span;
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Clone, Debug)]
-pub struct Vector(pub Vec<Expr>);
+pub struct Vector {
+    pub items: Vec<Expr>,
+    pub span: Option<Span>,
+    pub meta: Option<Rc<Map>>,
+}
+
+impl Vector {
+    pub fn new(items: Vec<Expr>) -> Self {
+        Vector { items, span: None, meta: None }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Vector { span: Some(span), ..self }
+    }
+}
 
 impl fmt::Display for Vector {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{}]", self.0.iter().join(" "))
+        write!(f, "[{}]", self.items.iter().join(" "))
     }
 }
 
 impl PartialEq for Vector {
     fn eq(&self, other: &Self) -> bool {
-    	self.0 == other.0
+    	self.items == other.items
     }
 }
@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use types::{Expr, List, Vector, Symbol};
+use types::{Expr, List, Map, Set, Vector, Symbol};
 use error::*;
 
 pub fn ensure_args(fn_name: &str, args: &[Expr], count: usize) -> Result<()> {
@@ -18,6 +18,11 @@ pub fn ensure_min_args(fn_name: &str, args: &[Expr], count: usize) -> Result<()>
     Ok(())
 }
 
+// The error for forms which come in pairs, such as a map's keys and values, when one is missing
+pub fn odd_forms(place: &str) -> String {
+    format!("expected an even number of forms in {}", place)
+}
+
 pub fn ensure_int(fn_name: &str, arg: &Expr) -> Result<i64> {
 	arg.int().ok_or_else(|| format!("#[{}] expected integer", fn_name).into())
 }
@@ -26,6 +31,14 @@ pub fn ensure_flt(fn_name: &str, arg: &Expr) -> Result<f64> {
 	arg.flt().ok_or_else(|| format!("#[{}] expected float", fn_name).into())
 }
 
+pub fn ensure_str<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a str> {
+	arg.str().ok_or_else(|| format!("#[{}] expected string", fn_name).into())
+}
+
+pub fn ensure_char(fn_name: &str, arg: &Expr) -> Result<char> {
+	arg.char().ok_or_else(|| format!("#[{}] expected character", fn_name).into())
+}
+
 pub fn ensure_sym<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a Symbol> {
 	arg.sym().ok_or_else(|| format!("#[{}] expected symbol", fn_name).into())
 }
@@ -37,3 +50,42 @@ pub fn ensure_list<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a List> {
 pub fn ensure_vector<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a Vector> {
 	arg.vector().ok_or_else(|| format!("#[{}] expected vector", fn_name).into())
 }
+
+pub fn ensure_map<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a Map> {
+	arg.map().ok_or_else(|| format!("#[{}] expected map", fn_name).into())
+}
+
+pub fn ensure_set<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a Set> {
+	arg.set().ok_or_else(|| format!("#[{}] expected set", fn_name).into())
+}
+
+// Reading and evaluating source for tests, as if it came from a file called test.tele
+#[cfg(test)]
+pub mod testing {
+    use env::Env;
+    use stream::{StringStream, TokenStream};
+    use types::Expr;
+    use {lexer, parser};
+
+    pub fn read(source: &str) -> Vec<Expr> {
+        let stream = StringStream::at(source, Some("test.tele".into()), 1);
+        let (tokens, _) = lexer::lex(stream).unwrap();
+        let (exprs, _) = parser::parse(TokenStream::new(tokens.into_iter())).unwrap();
+        exprs
+    }
+
+    // The value of each form in turn, all of which must succeed
+    pub fn eval_all(source: &str, env: &Env) -> Vec<Expr> {
+        read(source).iter().map(|expr| expr.eval(env.clone()).unwrap()).collect()
+    }
+
+    // The same, printed
+    pub fn eval_printed(source: &str, env: &Env) -> Vec<String> {
+        eval_all(source, env).iter().map(Expr::to_string).collect()
+    }
+
+    // The error from each form in turn, all of which must fail
+    pub fn eval_errors(source: &str, env: &Env) -> Vec<String> {
+        read(source).iter().map(|expr| expr.eval(env.clone()).unwrap_err().to_string()).collect()
+    }
+}